@@ -1,20 +1,135 @@
-use agb::display::object::{GraphicsMode, Sprite};
-use agb::display::tiled::RegularBackground;
-use agb::display::{Graphics, GraphicsFrame, Priority};
-use agb::fixnum::{num, Num};
+use agb::display::object::{AffineMatrixObject, AffineMode, GraphicsMode, Object, ObjectAffine, Sprite};
+use agb::display::AffineMatrix;
+use agb::display::tiled::RegularBackgroundId;
+use agb::display::WinIn;
+use agb::display::{GraphicsFrame, Priority};
+use agb::fixnum::{num, Num, Rect};
 use agb::println;
 use agb::{display::object::SpriteVram, fixnum::Vector2D};
 extern crate alloc;
+use alloc::format;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 
-use crate::{tile_gfx, Game, Menu, Tile};
+use crate::logic::{Cell, Ruleset, Shape, BEND, FIVE, PLUS, RING, SQUARE};
+use crate::logic::{Bombs, Jokers, Obstacles};
+use crate::hud::{NARROW_WIDTH, PANEL_WIDTH};
+use crate::lang::{Id, Language};
+use crate::text::{Align, Colour, Style, Text, COLUMNS};
+use crate::tween::{Ease, Progress};
+use crate::settings::{Bindings, Curve, Settings, Spawns, Speed, Theme, Vision};
+use crate::{tile_gfx, Appearing, Event, Game, Menu, Mode, Options, Pause, Setup, Tile, SETUP_ROWS};
 
 pub const TOP_LEFT: Vector2D<i32> = Vector2D::new(56, 16);
 pub const TILE_SIZE: u32 = 32;
+const HINT_LEAN: i32 = 3; //how far a hinted tile's copy sticks out
+const MARK_SIZE: i32 = 8; //patterns drawn over tiles
+
+//where a board sits on screen, tile_size is the distance between neighbouring tiles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub top_left: Vector2D<i32>,
+    pub tile_size: i32,
+    pub size: Vector2D<i32>, //columns and rows of one layer
+    pub layer_gap: i32, //pixels between layers drawn side by side
+}
+
+impl Layout {
+
+    //full size tiles centred on the screen, layered boards only fit with the small tiles
+    pub fn fit(shape: Shape) -> Self {
+
+        if shape.depth > 1 {
+            return LAYERS_LAYOUT;
+        }
+
+        let size = Vector2D::new(shape.width as i32, shape.height as i32);
+
+        Layout { top_left: (Vector2D::new(240, 160) - size * TILE_SIZE as i32) / 2, tile_size: TILE_SIZE as i32, size, layer_gap: 0 }
+
+    }
+
+    //just right of the board, kept on screen for wider boards
+    fn preview_pos(&self) -> Vector2D<i32> {
+        Vector2D::new((self.top_left.x + self.size.x * self.tile_size + 12).min(240 - TILE_SIZE as i32), self.top_left.y)
+    }
+
+    fn position(&self, pos: Vector2D<i32>) -> Vector2D<i32> {
+        let layer = pos.x.div_euclid(self.size.x);
+        self.top_left + pos * self.tile_size + Vector2D::new(layer * self.layer_gap, 0)
+    }
+
+    //hides objects outside the board (and the preview), so tiles crossing the edge of a wrapping board are cut in two
+    fn clip(&self, frame: &mut GraphicsFrame, layers: &[RegularBackgroundId]) {
+
+        let preview_pos = self.preview_pos();
+        let windows = frame.windows();
+
+        windows.win_in(WinIn::Win0).enable_objects().enable_blending().set_pos(Rect::new(self.top_left, self.size * self.tile_size));
+        windows.win_in(WinIn::Win1).enable_objects().enable_blending().set_pos(Rect::new(preview_pos, Vector2D::new(32, 32)));
+        windows.win_out().enable_blending();
+
+        for &bg_id in layers {
+            windows.win_in(WinIn::Win0).enable_background(bg_id);
+            windows.win_in(WinIn::Win1).enable_background(bg_id);
+            windows.win_out().enable_background(bg_id);
+        }
+
+    }
+
+    //where the hud shows this board's score, under its label in the left column if there's room or above the board if not
+    fn score_pos(&self) -> Vector2D<i32> {
+
+        match self.side_width() {
+            Some(width) => Vector2D::new(width * 4, self.top_left.y + 12),
+            None => {
+                let strip = self.strip();
+                Vector2D::new(strip.x * 8 + PANEL_WIDTH * 4, strip.y * 8 + 12)
+            },
+        }
+
+    }
+
+    //text columns the hud gets either side of the board, None if there's too little room and it goes above
+    pub fn side_width(&self) -> Option<i32> {
+        let room = (self.top_left.x / 8).min(PANEL_WIDTH);
+        (room >= NARROW_WIDTH).then_some(room)
+    }
+
+    //the text cell the strip of panels above the board starts at, labels on this row and numbers on the next.
+    //halfway down the gap above the board
+    pub fn strip(&self) -> Vector2D<i32> {
+        Vector2D::new(self.top_left.x / 8, self.top_left.y / 16 - 1)
+    }
+
+    //small boards use the 24 pixel tile set, drawn in the middle of 32 pixel sprites
+    fn is_small(&self) -> bool {
+        self.tile_size < TILE_SIZE as i32
+    }
+
+}
+
+//animations a board can have running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anim {
+    Slide, //tiles moving to where the last move put them
+    Blast, //bombs going off, starts once the slide ends
+    Grow, //new tiles scaling up from nothing
+    Pop, //merged tiles overshooting their size and settling
+}
+
+pub const FULL_LAYOUT: Layout = Layout { top_left: TOP_LEFT, tile_size: TILE_SIZE as i32, size: Vector2D::new(4, 4), layer_gap: 0 };
+
+//side by side boards matching gfx/bg_versus.aseprite
+pub const VERSUS_LAYOUTS: [Layout; 2] = [
+    Layout { top_left: Vector2D::new(6, 38), tile_size: 26, size: Vector2D::new(4, 4), layer_gap: 0 },
+    Layout { top_left: Vector2D::new(124, 38), tile_size: 26, size: Vector2D::new(4, 4), layer_gap: 0 },
+];
+
+//two layers where the versus boards would be
+pub const LAYERS_LAYOUT: Layout = Layout { top_left: Vector2D::new(6, 38), tile_size: 26, size: Vector2D::new(4, 4), layer_gap: 14 };
 
-pub const TILE_ANIMATION: i32 = 8;
-pub const START_ANIMATION: i32 = TILE_ANIMATION * 4;
 
 impl Menu {
 
@@ -46,13 +161,34 @@ impl Menu {
         for letter in self.text_one.iter_mut() {
             letter.show(frame);
         }
-        for letter in self.text_two.iter_mut() {
-            letter.show(frame);
+        if self.spelled() {
+            for letter in self.text_two.iter_mut() {
+                letter.show(frame);
+            }
         }
         self.button.show(frame);
     }
 
-    fn set_objs(&mut self) {
+    //the second row is drawn in big letters for game over and the test board, otherwise the mode's name is printed there
+    fn spelled(&self) -> bool {
+        self.game_over || self.test
+    }
+
+    //prints the mode's name across the middle of the title's second row, or blanks it while the big letters are up
+    pub fn print(&self, text: &mut Text, language: Language) {
+
+        let row = (TOP_LEFT.y + TILE_SIZE as i32 * 3 / 2) / 8;
+
+        if self.spelled() {
+            text.blank(Vector2D::new(0, row), COLUMNS);
+        } else {
+            text.print(Vector2D::new(0, row), COLUMNS, self.mode.name().text(language), Style::new(Colour::Accent, Align::Centre));
+        }
+
+    }
+
+    //also loads them again when the theme changes
+    pub fn set_objs(&mut self) {
 
         if self.game_over {
 
@@ -69,19 +205,14 @@ impl Menu {
                 letter.set_sprite(SpriteVram::from(tile_gfx::NAME.sprite(i)));
             }
     
-            if self.test {
-                for (i, letter) in self.text_two.iter_mut().enumerate() {
-                    letter.set_sprite(SpriteVram::from(tile_gfx::TEST.sprite(i)));
-                }
-            } else {
-                for (i, letter) in self.text_two.iter_mut().enumerate() {
-                    letter.set_sprite(SpriteVram::from(tile_gfx::PLAY.sprite(i)));
-                }
+            let word = if self.test { &tile_gfx::TEST } else { &tile_gfx::PLAY };
+            for (i, letter) in self.text_two.iter_mut().enumerate() {
+                letter.set_sprite(SpriteVram::from(word.sprite(i)));
             }
 
         }
 
-        
+        self.button.set_sprite(SpriteVram::from(tile_gfx::A.sprite(0)));
 
     }
 
@@ -92,219 +223,586 @@ impl Menu {
         }
     }
 
-    pub fn animate(&mut self, gfx: &mut Graphics, bg: &RegularBackground) {
+    pub fn mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    //the title letters flying apart as a game starts, drawn from copies so the menu is still in place for next time
+    pub fn show_start(&self, frame: &mut GraphicsFrame, progress: Progress) {
+
+        //the name splits down the middle
+        let spread = (Ease::In.apply(progress) * 240).floor();
 
-        self.animate_start(gfx, bg);
-        self.fade_in(gfx, bg, START_ANIMATION / 2);
+        for (i, letter) in self.text_one.iter().enumerate() {
+            let mut letter = letter.clone();
+            let side = if i < 2 { -1 } else { 1 };
+            letter.set_pos(letter.pos() + Vector2D::new(side * spread, 0));
+            letter.show(frame);
+        }
+
+        //the second row leaves upwards one letter after another
+        let rise = TOP_LEFT.y + TILE_SIZE as i32 * 2;
+
+        for (i, letter) in self.text_two.iter().enumerate().filter(|_| self.spelled()) {
+            let t = ((progress - Progress::new(i as i32) / 8) * 2).max(num!(0)).min(num!(1));
+            let mut letter = letter.clone();
+            letter.set_pos(letter.pos() - Vector2D::new(0, (Ease::In.apply(t) * rise).floor()));
+            letter.show(frame);
+        }
+
+        let mut button = self.button.clone();
+        button.set_sprite(SpriteVram::from(tile_gfx::A.sprite(1)));
+        button.show(frame);
+
+    }
 
+    //how far the printed mode name has risen as the game starts, it leaves all at once the way the letters leave in turn
+    pub fn start_rise(&self, progress: Progress) -> i32 {
+        let t = (progress * 2).max(num!(0)).min(num!(1));
+        (Ease::In.apply(t) * (TOP_LEFT.y + TILE_SIZE as i32 * 2)).floor()
     }
 
-    pub fn fade_in_out(&mut self, gfx: &mut Graphics, bg: &RegularBackground, time: i32) {
-        self.fade_out(gfx, bg, time);
-        self.fade_in(gfx, bg, time);
+}
+
+
+impl Setup {
+
+    //five tiles per row, rows centred on the screen
+    fn top_left() -> Vector2D<i32> {
+        Vector2D::new((240 - TILE_SIZE as i32 * 5) / 2, (160 - TILE_SIZE as i32 * SETUP_ROWS as i32) / 2)
     }
 
-    pub fn fade_in(&mut self, gfx: &mut Graphics, bg: &RegularBackground, time: i32) {
+    pub fn set(&mut self) {
+
+        let top_left = Self::top_left();
+
+        //loaded here rather than once so they're in the current theme
+        let sprites = [&tile_gfx::TWO, &tile_gfx::OBSTACLE, &tile_gfx::JOKER, &tile_gfx::BOMB, &tile_gfx::CELL];
+
+        for (row, icon) in self.icons.iter_mut().enumerate() {
+            icon.set_sprite(SpriteVram::from(sprites[row].sprite(0)));
+            icon.set_pos(top_left + Vector2D::new(0, TILE_SIZE as i32 * row as i32));
+        }
 
-        let blend_amts: [Num<u8, 4>; 5] = [
-            num!(1.0),
-            num!(0.75),
-            num!(0.50),
-            num!(0.25),
-            num!(0.0),
-        ];
+    }
 
-        let mut blend_lvl = 0;
+    fn option(&self, row: usize) -> Id {
+        match row {
+            0 => match self.ruleset {
+                Ruleset::Classic => Id::Classic,
+                Ruleset::Threes => Id::Threes,
+            },
+            1 => match self.modifiers.obstacles {
+                Obstacles::None => Id::None,
+                Obstacles::Fixed(_) => Id::Maze,
+                Obstacles::Survival(_) => Id::Walls,
+            },
+            2 => match self.modifiers.jokers {
+                Jokers::None => Id::None,
+                Jokers::Chance(_) => Id::Chance,
+                Jokers::Reward => Id::Reward,
+            },
+            3 => match self.modifiers.bombs {
+                Bombs::None => Id::None,
+                Bombs::Row => Id::Row,
+                Bombs::Column => Id::Column,
+                Bombs::Square => Id::Area,
+            },
+            _ => match self.modifiers.shape {
+                FIVE => Id::Five,
+                PLUS => Id::Plus,
+                BEND => Id::Bend,
+                RING => Id::Ring,
+                _ => Id::Square,
+            },
+        }
+    }
 
-        for anim_frame in 0..time {
+    //rows other than the selected one have their icon drawn see-through and their option in the darker colour.
+    //options are printed beside the icons, halfway down each row
+    pub fn show(&mut self, frame: &mut GraphicsFrame, text: &mut Text, language: Language) {
 
-            let mut frame = gfx.frame();
+        let top_left = Self::top_left();
 
-            let bg_id = bg.show(&mut frame);
+        for row in 0..SETUP_ROWS {
 
-            frame.blend().darken(blend_amts[blend_lvl]).enable_background(bg_id).enable_object();
+            let selected = row == self.cursor;
+            let at = (top_left + Vector2D::new(TILE_SIZE as i32 + 8, TILE_SIZE as i32 * row as i32 + TILE_SIZE as i32 / 2)) / 8;
+            let style = Style::new(if selected { Colour::Accent } else { Colour::Dark }, Align::Left);
 
-            if anim_frame % (time / 4) == 0 && blend_lvl < 4 {
-                blend_lvl += 1;
-            }
+            text.print(at, TILE_SIZE as i32 * 4 / 8, self.option(row).text(language), style);
 
-            frame.commit();
+            let icon = &mut self.icons[row];
+            icon.set_graphics_mode(if selected { GraphicsMode::Normal } else { GraphicsMode::AlphaBlending });
+            icon.show(frame);
 
         }
 
     }
 
-    pub fn fade_out(&mut self, gfx: &mut Graphics, bg: &RegularBackground, time: i32) {
+}
+
+
+impl Pause {
+
+    pub fn set(&mut self) {
+        self.cursor = 0;
+    }
+
+    //the selected entry sits in the middle of the screen, over the board
+    pub fn show(&self, text: &mut Text, language: Language) {
 
-        let blend_amts: [Num<u8, 4>; 5] = [
-            num!(0.0),
-            num!(0.25),
-            num!(0.50),
-            num!(0.75),
-            num!(1.0),
-        ];
+        let area = self.area();
+        let at = (area.top_left() + Vector2D::new(0, area.size.y / 2)) / 8;
 
-        let mut blend_lvl = 0;
+        text.print(at, area.size.x / 8, self.selected().name().text(language), Style::new(Colour::Accent, Align::Centre));
 
-        for anim_frame in 0..time {
+    }
 
-            let mut frame = gfx.frame();
+    //the part of the screen the overlay draws over
+    pub fn area(&self) -> Rect<i32> {
+        Rect::new(Vector2D::new((240 - TILE_SIZE as i32 * 4) / 2, (160 - TILE_SIZE as i32) / 2), Vector2D::new(TILE_SIZE as i32 * 4, TILE_SIZE as i32))
+    }
 
-            let bg_id = bg.show(&mut frame);
+}
 
-            frame.blend().darken(blend_amts[blend_lvl]).enable_background(bg_id).enable_object();
 
-            if anim_frame % (time / 4) == 0 && blend_lvl < 4 {
-                blend_lvl += 1;
-            }
+impl Options {
+
+    fn name(&self) -> Id {
+        [
+            Id::Speed,
+            Id::Music,
+            Id::Sound,
+            Id::Theme,
+            Id::Luck,
+            Id::Undo,
+            Id::Hints,
+            Id::Buttons,
+            Id::Calm,
+            Id::Queue,
+            Id::Skip,
+            Id::Easing,
+            Id::Span,
+            Id::Language,
+            Id::Vision,
+            Id::Patterns,
+        ][self.cursor]
+    }
+
+    //what the setting under the cursor is set to, numbers as they are and everything else from the string table
+    fn value(&self, settings: &Settings, language: Language) -> String {
 
-            frame.commit();
+        let number = match self.cursor {
+            1 => Some(settings.music_volume),
+            2 => Some(settings.sfx_volume),
+            5 => Some(settings.undo_depth),
+            6 => Some(settings.hints),
+            9 => Some(settings.queue_depth),
+            _ => None,
+        };
 
+        if let Some(n) = number {
+            return format!("{}", n);
         }
 
+        let on = |on: bool| if on { Id::On } else { Id::Off };
+
+        let id = match self.cursor {
+            0 => match settings.speed {
+                Speed::Slow => Id::Slow,
+                Speed::Normal => Id::Normal,
+                Speed::Fast => Id::Fast,
+            },
+            3 => match settings.theme {
+                Theme::Classic => Id::Classic,
+                Theme::Dark => Id::Dark,
+                Theme::Contrast => Id::Contrast,
+                Theme::Pastel => Id::Pastel,
+                Theme::Green => Id::Retro,
+            },
+            4 => match settings.spawns {
+                Spawns::Classic => Id::Normal,
+                Spawns::Generous => Id::Generous,
+                Spawns::Stingy => Id::Stingy,
+            },
+            7 => match settings.bindings {
+                Bindings::Standard => Id::Normal,
+                Bindings::Swapped => Id::Swapped,
+            },
+            8 => on(settings.reduced_motion),
+            10 => on(settings.skip_animations),
+            11 => match settings.curve {
+                Curve::Linear => Id::Linear,
+                Curve::Ease => Id::Smooth,
+                Curve::Back => Id::Overshoot,
+            },
+            12 => if settings.slide_by_distance { Id::Distance } else { Id::Same },
+            13 => match settings.language {
+                Language::English => Id::English,
+                Language::French => Id::French,
+            },
+            14 => match settings.vision {
+                Vision::Normal => Id::Normal,
+                Vision::Deuteranopia => Id::Deuteranopia,
+                Vision::Protanopia => Id::Protanopia,
+                Vision::Tritanopia => Id::Tritanopia,
+            },
+            _ => on(settings.patterns),
+        };
+
+        String::from(id.text(language))
+
     }
 
-    fn animate_start(&mut self, gfx: &mut Graphics, bg: &RegularBackground) {
+    //the setting's name above its value, both in the middle of the overlay. the language is the one being picked
+    //so switching it shows straight away
+    pub fn show(&self, text: &mut Text, settings: &Settings) {
 
-        for letter in self.text_one.iter_mut() {
-            letter.set_graphics_mode(GraphicsMode::AlphaBlending);
+        let area = self.area();
+        let (at, width) = (area.top_left() / 8, area.size.x / 8);
+        let language = settings.language;
+
+        text.print(at + Vector2D::new(0, 2), width, self.name().text(language), Style::new(Colour::Dark, Align::Centre));
+        text.print(at + Vector2D::new(0, 5), width, &self.value(settings, language), Style::new(Colour::Accent, Align::Centre));
+
+    }
+
+    pub fn area(&self) -> Rect<i32> {
+        Rect::new(Vector2D::new((240 - TILE_SIZE as i32 * 4) / 2, (160 - TILE_SIZE as i32 * 2) / 2), Vector2D::new(TILE_SIZE as i32 * 4, TILE_SIZE as i32 * 2))
+    }
+
+}
+
+
+//darkens whatever is under an overlay, a window keeps the overlay's own area at full brightness.
+//front is the overlay's own layers, which only show inside that window
+pub fn dim(frame: &mut GraphicsFrame, layers: &[RegularBackgroundId], keep: Rect<i32>, front: &[RegularBackgroundId]) {
+
+    darken(frame, layers, num!(0.5));
+
+    let windows = frame.windows();
+
+    windows.win_in(WinIn::Win0).enable_objects().set_pos(keep);
+    windows.win_out().enable_objects().enable_blending();
+
+    for &bg_id in layers {
+        windows.win_in(WinIn::Win0).enable_background(bg_id);
+        windows.win_out().enable_background(bg_id);
+    }
+
+    for &bg_id in front {
+        windows.win_in(WinIn::Win0).enable_background(bg_id);
+    }
+
+}
+
+//fades every layer and the objects towards black
+pub fn darken(frame: &mut GraphicsFrame, layers: &[RegularBackgroundId], amount: Num<u8, 4>) {
+
+    let blend = frame.blend();
+    let mut darken = blend.darken(amount);
+    darken.enable_object();
+
+    for &bg_id in layers {
+        darken.enable_background(bg_id);
+    }
+
+}
+
+
+impl Game {
+
+    //moves the board, swapping to the small tile set if the layout needs it
+    pub fn set_layout(&mut self, layout: Layout) {
+
+        self.layout = layout;
+        self.sprites = game_sprite_init(self.rules, layout);
+
+        for tile in self.board.iter_mut() {
+            tile.set_pos(layout);
+            tile.update_obj = true;
         }
 
-        for letter in self.text_two.iter_mut() {
-            letter.set_graphics_mode(GraphicsMode::AlphaBlending);
+        self.build_backdrop();
+
+    }
+
+    //every sprite in the game's colours again, after the theme changes
+    pub fn reload(&mut self) {
+
+        self.sprites = game_sprite_init(self.rules, self.layout);
+        self.build_backdrop();
+
+        for tile in self.board.iter_mut() {
+            tile.update_obj = true;
         }
 
-        self.button.set_sprite(SpriteVram::from(tile_gfx::A.sprite(1)));
-        self.button.set_graphics_mode(GraphicsMode::AlphaBlending);
+    }
 
-        let blend_amts: [Num<u8, 4>; 5] = [
-            num!(0.0),
-            num!(0.25),
-            num!(0.50),
-            num!(0.75),
-            num!(1.0),
-        ];
+    //irregular boards aren't part of the background, so every playable cell gets a sprite behind the tiles
+    pub fn build_backdrop(&mut self) {
 
-        let mut blend_lvl = 0;
+        self.backdrop.clear();
 
-        let mut in_motion = [false; 4];
-        
+        if self.modifiers.shape == SQUARE {
+            return;
+        }
 
-        for anim_frame in 0..START_ANIMATION {
+        for tile in self.board.iter().filter(|t| t.cell != Cell::Hole) {
+            let sprite = if self.layout.is_small() { &tile_gfx::CELL_SMALL } else { &tile_gfx::CELL };
+            let mut cell = Object::new(SpriteVram::from(sprite.sprite(0)));
+            cell.set_pos(self.layout.position(tile.pos));
+            self.backdrop.push(cell);
+        }
 
-            let mut frame = gfx.frame();
+    }
 
-            let bg_id = bg.show(&mut frame);
+    //shown after the tiles so it ends up underneath them
+    fn show_backdrop(&mut self, frame: &mut GraphicsFrame) {
 
-            for (i, letter) in self.text_one.iter_mut().enumerate() {
+        for cell in self.backdrop.iter_mut() {
+            cell.show(frame);
+        }
 
-                match i {
-                    0..=1 => letter.set_pos(letter.pos() + Vector2D::new(-12, 0)),
-                    2..=3 => letter.set_pos(letter.pos() + Vector2D::new(12, 0)),
-                    _ => letter.set_pos(letter.pos()), //UNREACHABLE
-                };
+    }
+
+    //dimmed boards are drawn see-through, the frame still needs object transparency enabling
+    pub fn set_dimmed(&mut self, dimmed: bool) {
 
+        self.mode = if dimmed { GraphicsMode::AlphaBlending } else { GraphicsMode::Normal };
+
+        for tile in self.board.iter_mut() {
+            tile.object.set_graphics_mode(self.mode);
+            if let Some(mark) = tile.mark.as_mut() {
+                mark.set_graphics_mode(self.mode);
             }
+        }
 
-            for (i, letter) in self.text_two.iter_mut().enumerate() {
+    }
 
-                if (anim_frame % 8) / 2 == i as i32 {
-                    //println!("frame: {}, frame % 8 / 2: {}", anim_frame, (anim_frame % 8) / 2);
-                    in_motion[i] = true;
-                }
+    //what gets drawn depends on the running animations: sliding tiles keep their old sprite until they land,
+    //tiles the move made stay hidden until then, cleared cells show the blast, and new or merged tiles are scaled
+    pub fn show_tiles(&mut self, frame: &mut GraphicsFrame) {
 
-                if in_motion[i] == true && !is_above(letter.pos()) {
-                    if letter.pos().y == TOP_LEFT.y + TILE_SIZE as i32 {
-                        letter.set_pos(letter.pos() + Vector2D::new(0, 6));
-                    } else {
-                        letter.set_pos(letter.pos() + Vector2D::new(0, -8));
-                    }
-                }
+        let slide = self.tweens.progress(Anim::Slide);
+        let blast = self.tweens.progress(Anim::Blast);
 
-            }
+        //one matrix for each scaling animation, shared by every tile in it
+        let grow = self.tweens.progress(Anim::Grow).map(|p| p.max(num!(0.125))).map(|s| (s, scale_matrix(s)));
+        let pop = self.tweens.progress(Anim::Pop).map(|p| Progress::new(1) + (Progress::new(1) - (p * 2 - 1).abs()) / 5).map(|s| (s, scale_matrix(s)));
 
-            if anim_frame >= START_ANIMATION / 4 {
+        //the slide runs linearly, each tile eases over its own part of it
+        let longest = self.longest_slide();
+        let width = self.modifiers.shape.width as i32;
+        let ease = self.settings.curve.ease();
 
-                frame.blend().darken(blend_amts[blend_lvl]).enable_background(bg_id).enable_object();
+        self.popups.show(frame);
 
-                if anim_frame < START_ANIMATION / 2 {
-                    if anim_frame % 2 == 0 && blend_lvl < 4 {
-                        blend_lvl += 1;
-                    }
-                }
-                
+        for tile in self.board.iter_mut() {
+
+            if let (Some(progress), Some(destination)) = (slide, tile.animate) {
+                let progress = if self.settings.slide_by_distance {
+                    (progress * longest / slide_cells(tile.pos, destination, width)).min(Progress::new(1))
+                } else {
+                    progress
+                };
+                tile.show_sliding(destination, ease.apply(progress), frame, self.layout, self.modifiers.wrap);
+                continue;
+            }
+
+            if slide.is_some() && tile.appearing.is_some() {
+                continue;
+            }
+
+            if tile.update_obj {
+                tile.set_obj(self.sprites.clone(), self.rules, self.settings.patterns, self.mode);
             }
 
-            self.show(&mut frame);
+            let scale = match tile.appearing {
+                Some(Appearing::Spawned) => grow.as_ref(),
+                Some(Appearing::Merged) => pop.as_ref(),
+                _ => None,
+            };
 
-            frame.commit();
+            match (blast, scale) {
+                (Some(progress), _) if tile.exploding => tile.show_blast(progress, frame, self.layout),
+                (_, Some(scaling)) => tile.show_scaled(scaling, self.mode, frame, self.layout, &self.sprites, self.rules),
+                _ => tile.show(frame, self.layout),
+            }
 
         }
 
+        self.show_hint(frame);
+        self.show_backdrop(frame);
+
     }
 
+    //tiles the hint would move have a copy of themselves peeking out from behind, nudged the way they'd go
+    fn show_hint(&self, frame: &mut GraphicsFrame) {
 
-}
+        for (index, to) in self.hinted() {
 
+            let tile = &self.board[index];
+            let lean = Vector2D::new((to.x - tile.pos.x).signum(), (to.y - tile.pos.y).signum()) * HINT_LEAN;
 
-impl Game {
+            let mut copy = tile.object.clone();
+            copy.set_pos(self.layout.position(tile.pos) + lean);
+            copy.show(frame);
 
-    fn update_tile_objs(&mut self) {
+        }
 
-        //iterates through every tile with the update_obj flag set
-        for tile in &mut self.board.iter_mut().filter(|t| t.update_obj) {
+    }
 
-            tile.set_obj(self.sprites.clone());
+    //shows the upcoming tile to the right of the board, threes only
+    pub fn show_preview(&mut self, frame: &mut GraphicsFrame) {
 
+        if self.rules != Ruleset::Threes {
+            return;
         }
 
-    } 
+        if let Some(sprite) = cell_sprite(self.next, &self.sprites, self.rules) {
+            self.preview.set_sprite(sprite);
+            self.preview.set_pos(self.layout.preview_pos());
+            self.preview.show(frame);
+        }
 
+    }
 
-    pub fn show_tiles(&mut self, frame: &mut GraphicsFrame) {
+    //tiles sliding off the edge of a wrapping board are cut off there
+    pub fn clip(&self, frame: &mut GraphicsFrame, layers: &[RegularBackgroundId]) {
 
-        for tile in &mut self.board.iter_mut() {
+        if self.modifiers.wrap && self.tweens.is_running(Anim::Slide) {
+            self.layout.clip(frame, layers);
+        }
 
-            if tile.update_obj {
-                tile.set_obj(self.sprites.clone());
-            }
-            
-            tile.show(frame);
+    }
+
+    //one frame on for every animation on the board, then whatever follows the ones that finished
+    pub fn animate(&mut self) {
+        self.tweens.update();
+        self.handle_events();
+        self.popups.update();
+        self.count_score();
+    }
 
+    //the score the hud shows catches up with the real one a little each frame, faster the further behind it is
+    fn count_score(&mut self) {
+
+        let gap = self.score.abs_diff(self.shown_score);
+        let step = (gap / 8).max(1).min(gap);
+
+        if self.score > self.shown_score {
+            self.shown_score += step;
+        } else {
+            self.shown_score -= step;
         }
 
     }
 
-    pub fn animate_move_tiles(&mut self, gfx: &mut Graphics, bg: &RegularBackground) {
+    //jumps straight to the end of everything, including animations that only start once others finish
+    pub fn finish_animations(&mut self) {
 
-        for _i in 0..TILE_ANIMATION {
+        while self.tweens.is_busy() {
+            self.tweens.finish();
+            self.handle_events();
+        }
 
-            let mut frame = gfx.frame();
+    }
 
-            for tile in &mut self.board.iter_mut() {
+    pub fn is_animating(&self) -> bool {
+        self.tweens.is_busy()
+    }
 
-                if let Some(destination) = tile.animate {
+    //cells crossed by the furthest moving tile, sets how long a slide takes when that depends on distance
+    pub fn longest_slide(&self) -> i32 {
 
-                    tile.animate_move(destination, &mut frame);
+        let width = self.modifiers.shape.width as i32;
 
-                } else if !tile.appearing {
+        self.board.iter().filter_map(|t| t.animate.map(|d| slide_cells(t.pos, d, width))).max().unwrap_or(1)
 
-                    tile.show(&mut frame); //so that all tiles are displayed during animation frames
+    }
 
-                }
-                
-            }
+    //new and merged tiles wait for the slide to land before scaling in
+    pub fn start_arrivals(&mut self) {
 
-            bg.show(&mut frame);
+        if self.tweens.is_running(Anim::Slide) {
+            return;
+        }
 
-            frame.commit();
-        
+        if self.board.iter().any(|t| t.appearing == Some(Appearing::Spawned)) {
+            self.events.push(Event::Spawn);
+            self.tweens.start(Anim::Grow, self.settings.tile_animation(), Ease::Out);
         }
+        if self.board.iter().any(|t| t.appearing == Some(Appearing::Merged)) {
+            self.tweens.start(Anim::Pop, self.settings.tile_animation(), Ease::Linear);
+        }
+
+    }
+
+    //a "+N" over each merge, and the move's total heads off to the score
+    fn pop_gains(&mut self) {
 
-        //reset animation and appearing flag once completed
-        for tile in self.board.iter_mut().filter(|t| t.animate.is_some() || t.appearing) {
-            tile.animate = None;
-            tile.appearing = false;
+        let still = self.settings.reduced_motion;
+        let half = Vector2D::new(self.layout.tile_size / 2, self.layout.tile_size / 2);
+        let mut total = 0;
+        let mut highest = 0;
+
+        for (index, value) in self.gains.drain(..) {
+            self.popups.rise(value as u32, self.layout.position(self.board[index].pos) + half, still);
+            total += value as u32;
+            highest = highest.max(value);
+        }
+
+        //one merge sound for the move, pitched for its best merge
+        if highest > 0 {
+            self.events.push(Event::Merge(highest));
+        }
+
+        if total > 0 {
+            let centre = self.layout.top_left + self.layout.size * self.layout.tile_size / 2;
+            self.popups.fly(total, centre, self.layout.score_pos(), still);
+        }
+
+    }
+
+    fn arrived(&mut self, appearing: Appearing) {
+
+        for tile in self.board.iter_mut().filter(|t| t.appearing == Some(appearing)) {
+            tile.appearing = None;
+        }
+
+    }
+
+    fn handle_events(&mut self) {
+
+        for event in self.tweens.events() {
+            match event {
+                Anim::Slide => {
+                    for tile in self.board.iter_mut() {
+                        tile.animate = None;
+                        if tile.appearing == Some(Appearing::Moved) {
+                            tile.appearing = None;
+                        }
+                    }
+
+                    self.start_arrivals();
+                    self.pop_gains();
+
+                    //bombs go off once everything has landed
+                    if self.board.iter().any(|t| t.exploding) {
+                        self.tweens.start(Anim::Blast, self.settings.tile_animation() * 2, Ease::Linear);
+                    }
+                },
+                Anim::Blast => {
+                    for tile in self.board.iter_mut().filter(|t| t.exploding) {
+                        tile.exploding = false;
+                        tile.update_obj = true;
+                    }
+                },
+                Anim::Grow => self.arrived(Appearing::Spawned),
+                Anim::Pop => self.arrived(Appearing::Merged),
+            }
         }
 
     }
@@ -314,35 +812,103 @@ impl Game {
 impl Tile {
 
     //reset all objects to default position
-    pub fn set_pos(&mut self) {
-        let (x, y) = (TOP_LEFT.x as u32 + self.pos.x as u32 * TILE_SIZE, TOP_LEFT.y as u32 + self.pos.y as u32 * TILE_SIZE);
-        self.object.set_pos(Vector2D::new(x as i32, y as i32));
+    pub fn set_pos(&mut self, layout: Layout) {
+        self.object.set_pos(layout.position(self.pos));
     }
 
-    fn animate_move(&mut self, destination: Vector2D<i32>, frame: &mut GraphicsFrame) {
+    //draws the tile part way from its cell to destination
+    fn show_sliding(&mut self, destination: Vector2D<i32>, progress: Progress, frame: &mut GraphicsFrame, layout: Layout, wrap: bool) {
 
-        let og_position_adjusted = self.pos * TILE_SIZE as i32 + TOP_LEFT;
-        let destination_adjusted = destination * TILE_SIZE as i32 + TOP_LEFT;
+        let start = layout.position(self.pos);
+        let travel: Vector2D<Progress> = (layout.position(destination) - start).change_base();
 
-        self.object.set_pos(self.object.pos() + (destination_adjusted - og_position_adjusted) / (TILE_ANIMATION + 1));
+        self.object.set_pos(start + (travel * progress).floor());
         self.object.set_priority(Priority::P0);
+        self.show_mark(frame, layout);
         self.object.show(frame);
 
+        //a tile sliding off the edge of a wrapping board is also drawn coming back in on the other side
+        let size = layout.size * layout.tile_size;
+        let offset = self.object.pos() - layout.top_left;
+        let seam = |along: i32, size: i32| if along < 0 { size } else if along > size - layout.tile_size { -size } else { 0 };
+        let wrapped = Vector2D::new(seam(offset.x, size.x), seam(offset.y, size.y));
+
+        if wrap && wrapped != Vector2D::new(0, 0) {
+            let mut copy = self.object.clone();
+            copy.set_pos(self.object.pos() + wrapped);
+            copy.show(frame);
+        }
+
     }
 
-    fn set_obj(&mut self, sprites: Vec<SpriteVram>) {
+    fn show_blast(&mut self, progress: Progress, frame: &mut GraphicsFrame, layout: Layout) {
+
+        let frames = tile_gfx::EXPLOSION.sprites().len();
+        let index = ((progress * frames as i32).floor() as usize).min(frames - 1);
 
-        if let Some(sprite_index) = value_to_sprite_index(self.value) {
-            self.object.set_sprite(sprites[sprite_index].clone());
+        self.object.set_sprite(SpriteVram::from(tile_gfx::EXPLOSION.sprite(index)));
+        self.set_pos(layout);
+        self.object.show(frame);
+
+    }
+
+    //draws the tile through an affine matrix, doubled so the overshoot isn't clipped to the sprite.
+    //its pattern goes through the same matrix, moved in towards the middle of the tile as it shrinks
+    fn show_scaled(&self, (scale, matrix): &(Progress, AffineMatrixObject), mode: GraphicsMode, frame: &mut GraphicsFrame, layout: Layout, sprites: &[SpriteVram], rules: Ruleset) {
+
+        let Some(sprite) = cell_sprite(self.cell, sprites, rules) else {
+            return;
+        };
+
+        let half = Vector2D::new(TILE_SIZE as i32 / 2, TILE_SIZE as i32 / 2);
+        let centre = layout.position(self.pos) + half;
+
+        if let Some(sprite) = self.mark.as_ref().and_then(|_| mark_sprite(self.cell, rules)) {
+            let offset: Vector2D<Progress> = (mark_corner(layout) + Vector2D::new(MARK_SIZE / 2, MARK_SIZE / 2) - half).change_base();
+            let mut mark = ObjectAffine::new(sprite, matrix.clone(), AffineMode::AffineDouble);
+            mark.set_pos(centre + (offset * *scale).floor() - Vector2D::new(MARK_SIZE, MARK_SIZE));
+            mark.set_graphics_mode(mode);
+            mark.show(frame);
         }
+
+        let mut object = ObjectAffine::new(sprite, matrix.clone(), AffineMode::AffineDouble);
+        object.set_pos(centre - half * 2);
+        object.set_graphics_mode(mode);
+        object.show(frame);
+
+    }
+
+    fn set_obj(&mut self, sprites: Vec<SpriteVram>, rules: Ruleset, patterns: bool, mode: GraphicsMode) {
+
+        if let Some(sprite) = cell_sprite(self.cell, &sprites, rules) {
+            self.object.set_sprite(sprite);
+        }
+
+        self.mark = mark_sprite(self.cell, rules).filter(|_| patterns).map(|sprite| {
+            let mut mark = Object::new(sprite);
+            mark.set_graphics_mode(mode);
+            mark
+        });
+
         self.update_obj = false;
 
     }
 
-    fn show(&mut self, frame: &mut GraphicsFrame) {
-        self.set_pos();
+    //the pattern goes in the tile's top left corner, shown before the tile so it's drawn over it
+    fn show_mark(&mut self, frame: &mut GraphicsFrame, layout: Layout) {
+
+        if let Some(mark) = self.mark.as_mut() {
+            mark.set_pos(self.object.pos() + mark_corner(layout));
+            mark.show(frame);
+        }
+
+    }
+
+    fn show(&mut self, frame: &mut GraphicsFrame, layout: Layout) {
+        self.set_pos(layout);
 
-        if self.value != 0 {
+        if !self.cell.is_empty() && self.cell != Cell::Hole {
+            self.show_mark(frame, layout);
             self.object.show(frame);
         }
     }
@@ -350,7 +916,17 @@ impl Tile {
 }
 
 
-pub fn game_sprite_init() -> Vec<SpriteVram> {
+pub fn game_sprite_init(rules: Ruleset, layout: Layout) -> Vec<SpriteVram> {
+
+    //only classic tiles come in the small size
+    if layout.is_small() {
+        return (0..tile_gfx::SMALL.sprites().len()).map(|i| SpriteVram::from(tile_gfx::SMALL.sprite(i))).collect();
+    }
+
+    if rules == Ruleset::Threes {
+        return (0..tile_gfx::THREES.sprites().len()).map(|i| SpriteVram::from(tile_gfx::THREES.sprite(i))).collect();
+    }
+
     vec![
         SpriteVram::from(tile_gfx::TWO.sprite(0)),
         SpriteVram::from(tile_gfx::FOUR.sprite(0)),
@@ -363,17 +939,67 @@ pub fn game_sprite_init() -> Vec<SpriteVram> {
         SpriteVram::from(tile_gfx::FIVETWELVE.sprite(0)),
         SpriteVram::from(tile_gfx::TENTWENTYFOUR.sprite(0)),
         SpriteVram::from(tile_gfx::TWENTYFOURTYEIGHT.sprite(0)),
+        SpriteVram::from(tile_gfx::FOUROHNINETYSIX.sprite(0)),
+        SpriteVram::from(tile_gfx::EIGHTONENINETYTWO.sprite(0)),
+        SpriteVram::from(tile_gfx::SIXTEENK.sprite(0)),
+        SpriteVram::from(tile_gfx::THIRTYTWOK.sprite(0)),
     ]
 }
 
 
 
-pub fn value_to_sprite_index(value: u16) -> Option<usize> {
+//cells between a tile and where it slides to, layers sit side by side so a jump between them counts as one
+fn slide_cells(from: Vector2D<i32>, to: Vector2D<i32>, width: i32) -> i32 {
+    let travel = to - from;
+    (travel.x.abs() / width + travel.x.abs() % width + travel.y.abs()).max(1)
+}
+
+//affine matrices shrink by the scale they are given, so this takes the inverse
+fn scale_matrix(scale: Progress) -> AffineMatrixObject {
+    let inverse = Progress::new(1) / scale;
+    AffineMatrixObject::new(AffineMatrix::from_scale(Vector2D::new(inverse, inverse)))
+}
+
+//one pattern per sprite game_sprite_init gives, so each value gets its own whatever the tile set
+fn mark_sprite(cell: Cell, rules: Ruleset) -> Option<SpriteVram> {
+    match cell {
+        Cell::Number(value) => value_to_sprite_index(value, rules).map(|i| SpriteVram::from(tile_gfx::MARKS.sprite(i % tile_gfx::MARKS.sprites().len()))),
+        _ => None,
+    }
+}
+
+//where a pattern sits inside its tile, in from the top left corner of the sprite
+fn mark_corner(layout: Layout) -> Vector2D<i32> {
+    if layout.is_small() { Vector2D::new(5, 5) } else { Vector2D::new(3, 3) }
+}
+
+//special cells have a sprite of their own, numbers look theirs up in the game's sprite set
+fn cell_sprite(cell: Cell, sprites: &[SpriteVram], rules: Ruleset) -> Option<SpriteVram> {
+    match cell {
+        Cell::Empty | Cell::Hole => None,
+        Cell::Obstacle => Some(SpriteVram::from(tile_gfx::OBSTACLE.sprite(0))),
+        Cell::Joker => Some(SpriteVram::from(tile_gfx::JOKER.sprite(0))),
+        Cell::Bomb => Some(SpriteVram::from(tile_gfx::BOMB.sprite(0))),
+        Cell::Number(value) => value_to_sprite_index(value, rules).map(|i| sprites[i.min(sprites.len() - 1)].clone()), //past the last sprite they share it
+    }
+}
+
+pub fn value_to_sprite_index(value: u16, rules: Ruleset) -> Option<usize> {
 
     if value == 0 {
         
         None
 
+    } else if rules == Ruleset::Threes {
+
+        //1 and 2 come first, then 3 * 2^n
+        if value < 3 {
+            Some((value - 1) as usize)
+        } else {
+            let log2_floor = 15 - (value / 3).leading_zeros();
+            Some((log2_floor + 2) as usize)
+        }
+
     } else {
 
         let log2_floor = 15 - value.leading_zeros();
@@ -383,9 +1009,3 @@ pub fn value_to_sprite_index(value: u16) -> Option<usize> {
     
 }
 
-fn is_above(pos: Vector2D<i32>) -> bool {
-
-    pos.y > 128 && pos.y < 192
-
-}
-
@@ -0,0 +1,110 @@
+use agb::display::tiled::RegularBackgroundId;
+use agb::display::{GraphicsFrame, Priority};
+use agb::fixnum::Vector2D;
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+
+use crate::graphics::{Layout, TILE_SIZE};
+use crate::lang::{Id, Language};
+use crate::logic::Ruleset;
+use crate::text::{Align, Colour, Style, Text, COLUMNS};
+use crate::Game;
+
+
+//labels on the left of each panel, numbers on the right like a scoreboard
+const LABEL: Style = Style::new(Colour::Dark, Align::Left);
+const VALUE: Style = Style::new(Colour::Accent, Align::Right);
+
+//tiles across each column either side of the board, numbers past this width are capped
+pub const PANEL_WIDTH: i32 = 6;
+
+//five wide boards leave a column short, narrower than this and the panels move above the board
+pub const NARROW_WIDTH: i32 = 5;
+
+//score, best, moves, time and highest tile drawn on their own background in the columns either side of the board,
+//or in a strip above it when the board is too wide
+pub struct Hud {
+    text: Text,
+}
+
+impl Hud {
+
+    pub fn new() -> Self {
+        Self { text: Text::new(Priority::P0) }
+    }
+
+    //shown before the board so it sits in front of it
+    pub fn show(&mut self, frame: &mut GraphicsFrame, game: &Game, best: u32, language: Language) -> RegularBackgroundId {
+
+        let width = game.layout.side_width().unwrap_or(PANEL_WIDTH);
+        let seconds = game.time / 60;
+        let highest = game.board.iter().map(|t| t.cell.value()).max().unwrap_or(0);
+
+        let panels = [
+            (Id::Score, capped(game.shown_score, width)),
+            (Id::Best, capped(best, width)),
+            (Id::Tile, capped(highest as u32, width)),
+            (Id::Moves, capped(game.moves, width)),
+            (Id::Time, format!("{}:{:02}", seconds / 60, seconds % 60)),
+        ];
+
+        match game.layout.side_width() {
+            Some(width) => self.columns(game, width, &panels, language),
+            None => self.strip(game.layout, &panels, language),
+        }
+
+        self.text.show(frame)
+
+    }
+
+    //the first three panels down the left of the board and the rest down the right
+    fn columns(&mut self, game: &Game, width: i32, panels: &[(Id, String)], language: Language) {
+
+        let top = game.layout.top_left.y / 8;
+        let right = COLUMNS - width;
+
+        //threes shows the next tile at the top of the right hand column, the panels there start a row below it
+        let right_top = match game.rules {
+            Ruleset::Threes => top + TILE_SIZE as i32 / 8 + 1,
+            Ruleset::Classic => top,
+        };
+
+        for (i, (label, value)) in panels.iter().enumerate() {
+            let at = if i < 3 { Vector2D::new(0, top + 3 * i as i32) } else { Vector2D::new(right, right_top + 3 * (i as i32 - 3)) };
+            self.text.print(at, width, label.text(language), LABEL);
+            self.text.print(at + Vector2D::new(0, 1), width, value, VALUE);
+        }
+
+    }
+
+    //two player boards sit too close together for the side columns, each has its score above it instead
+    pub fn show_scores(&mut self, frame: &mut GraphicsFrame, games: &[Game], language: Language) -> RegularBackgroundId {
+
+        for game in games {
+            self.strip(game.layout, &[(Id::Score, capped(game.shown_score, PANEL_WIDTH))], language);
+        }
+
+        self.text.show(frame)
+
+    }
+
+    //panels side by side in the strip above a board, starting from its left edge
+    fn strip(&mut self, layout: Layout, panels: &[(Id, String)], language: Language) {
+
+        let start = layout.strip();
+
+        for (i, (label, value)) in panels.iter().enumerate() {
+            let at = start + Vector2D::new(PANEL_WIDTH * i as i32, 0);
+            self.text.print(at, PANEL_WIDTH, label.text(language), LABEL);
+            self.text.print(at + Vector2D::new(0, 1), PANEL_WIDTH, value, VALUE);
+        }
+
+    }
+
+}
+
+//the largest number that fits in a panel this many columns wide
+fn capped(value: u32, width: i32) -> String {
+    format!("{}", value.min(10u32.pow(width as u32) - 1))
+}
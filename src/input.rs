@@ -0,0 +1,98 @@
+use agb::input::{Button, ButtonController};
+extern crate alloc;
+use alloc::collections::VecDeque;
+
+use crate::logic::Direction;
+use crate::settings::Settings;
+
+
+//the buttons, plus any moves pressed while the board was too busy animating to take them
+pub struct Input {
+    pub buttons: ButtonController,
+    queue: VecDeque<Direction>,
+    depth: usize, //moves that can wait, 0 drops anything pressed during an animation
+    skip: bool, //a new press cuts the running animation short and is played straight away
+}
+
+impl Input {
+
+    pub fn new(settings: &Settings) -> Self {
+
+        let mut input = Self { buttons: ButtonController::new(), queue: VecDeque::new(), depth: 0, skip: false };
+        input.configure(settings);
+        input
+
+    }
+
+    //picks up the queue settings, dropping anything past the new depth
+    pub fn configure(&mut self, settings: &Settings) {
+        self.depth = settings.queue_depth as usize;
+        self.skip = settings.skip_animations;
+        self.queue.truncate(self.depth);
+    }
+
+    pub fn update(&mut self) {
+        self.buttons.update();
+    }
+
+    //the direction pressed this frame, R and L move between layers
+    pub fn direction(&self) -> Option<Direction> {
+
+        if self.buttons.is_just_pressed(Button::UP) {
+            Some(Direction::Up)
+        } else if self.buttons.is_just_pressed(Button::DOWN) {
+            Some(Direction::Down)
+        } else if self.buttons.is_just_pressed(Button::LEFT) {
+            Some(Direction::Left)
+        } else if self.buttons.is_just_pressed(Button::RIGHT) {
+            Some(Direction::Right)
+        } else if self.buttons.is_just_pressed(Button::R) {
+            Some(Direction::In)
+        } else if self.buttons.is_just_pressed(Button::L) {
+            Some(Direction::Out)
+        } else {
+            None
+        }
+
+    }
+
+    //the move to make this frame, oldest queued first so presses play out in the order they were made
+    pub fn next_move(&mut self) -> Option<Direction> {
+
+        let pressed = self.direction();
+
+        match self.queue.pop_front() {
+            Some(queued) => {
+                if let Some(dir) = pressed {
+                    self.queue.push_back(dir);
+                }
+                Some(queued)
+            },
+            None => pressed,
+        }
+
+    }
+
+    //called each frame the board is still animating instead of next_move, queues any press.
+    //returns true if the animation should be cut short, the press is then left for next_move
+    pub fn hold(&mut self) -> bool {
+
+        match self.direction() {
+            Some(_) if self.skip => true,
+            Some(dir) => {
+                if self.queue.len() < self.depth {
+                    self.queue.push_back(dir);
+                }
+                false
+            },
+            None => false,
+        }
+
+    }
+
+    //forgets queued moves, for when the board they were meant for changes
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+}
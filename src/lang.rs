@@ -0,0 +1,170 @@
+//languages the text layer can be shown in, picked on the options screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    English,
+    French,
+}
+
+//every string the game prints, looked up in STRINGS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Id {
+    Score,
+    Best,
+    Tile,
+    Moves,
+    Time,
+    PlayerOne,
+    Waiting,
+    //modes on the title screen, the first two also name the rulesets on the setup screen
+    Classic,
+    Threes,
+    Maze,
+    Walls,
+    Wild,
+    Wrap,
+    Deep,
+    Custom,
+    Duel,
+    Race,
+    Link,
+    //setup screen options
+    None,
+    Chance,
+    Reward,
+    Row,
+    Column,
+    Area,
+    Square,
+    Five,
+    Plus,
+    Bend,
+    Ring,
+    //pause menu
+    Resume,
+    Restart,
+    Undo,
+    Options,
+    Quit,
+    //options screen, each setting's name then the values they take
+    Speed,
+    Music,
+    Sound,
+    Theme,
+    Luck,
+    Hints,
+    Buttons,
+    Calm,
+    Queue,
+    Skip,
+    Easing,
+    Span,
+    Language,
+    Vision,
+    Patterns,
+    Slow,
+    Normal,
+    Fast,
+    Dark,
+    Contrast,
+    Pastel,
+    Retro,
+    Stingy,
+    Generous,
+    Swapped,
+    On,
+    Off,
+    Linear,
+    Smooth,
+    Overshoot,
+    Same,
+    Distance,
+    English,
+    French,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+//one row per id in the order above, one column per language. the hud panels are 6 characters wide,
+//anything longer gets shortened there so keep labels short where a translation allows
+const STRINGS: [[&str; 2]; 71] = [
+    ["SCORE", "SCORE"],
+    ["BEST", "RECORD"],
+    ["TILE", "TUILE"],
+    ["MOVES", "COUPS"],
+    ["TIME", "DURÉE"],
+    ["PLAYER 1", "JOUEUR 1"],
+    ["WAITING...", "EN ATTENTE..."],
+    ["CLASSIC", "CLASSIQUE"],
+    ["THREES", "THREES"],
+    ["MAZE", "LABYRINTHE"],
+    ["WALLS", "MURS"],
+    ["WILD", "JOKERS"],
+    ["WRAP", "SANS BORDS"],
+    ["DEEP", "PROFOND"],
+    ["CUSTOM", "PERSO"],
+    ["DUEL", "DUEL"],
+    ["RACE", "COURSE"],
+    ["LINK", "CÂBLE"],
+    ["NONE", "AUCUN"],
+    ["RANDOM", "AU HASARD"],
+    ["REWARD", "RÉCOMPENSE"],
+    ["ROW", "LIGNE"],
+    ["COLUMN", "COLONNE"],
+    ["AREA", "ZONE"],
+    ["4X4", "4X4"],
+    ["5X5", "5X5"],
+    ["PLUS", "CROIX"],
+    ["BEND", "COUDE"],
+    ["RING", "ANNEAU"],
+    ["RESUME", "REPRENDRE"],
+    ["RESTART", "RECOMMENCER"],
+    ["UNDO", "ANNULER"],
+    ["OPTIONS", "OPTIONS"],
+    ["QUIT", "QUITTER"],
+    ["SPEED", "VITESSE"],
+    ["MUSIC", "MUSIQUE"],
+    ["SOUND", "SONS"],
+    ["THEME", "THÈME"],
+    ["LUCK", "CHANCE"],
+    ["HINTS", "INDICES"],
+    ["BUTTONS", "BOUTONS"],
+    ["REDUCED MOTION", "MOUVEMENT RÉDUIT"],
+    ["MOVES AHEAD", "COUPS D'AVANCE"],
+    ["SKIP SLIDES", "ABRÉGER"],
+    ["EASING", "COURBE"],
+    ["SLIDE LENGTH", "DURÉE GLISSEMENT"],
+    ["LANGUAGE", "LANGUE"],
+    ["COLOUR VISION", "VISION COULEURS"],
+    ["PATTERNS", "MOTIFS"],
+    ["SLOW", "LENT"],
+    ["NORMAL", "NORMAL"],
+    ["FAST", "RAPIDE"],
+    ["DARK", "SOMBRE"],
+    ["HIGH CONTRAST", "CONTRASTE"],
+    ["PASTEL", "PASTEL"],
+    ["RETRO", "RÉTRO"],
+    ["STINGY", "AVARE"],
+    ["GENEROUS", "GÉNÉREUX"],
+    ["SWAPPED", "INVERSÉS"],
+    ["ON", "OUI"],
+    ["OFF", "NON"],
+    ["LINEAR", "LINÉAIRE"],
+    ["SMOOTH", "DOUCE"],
+    ["OVERSHOOT", "DÉPASSEMENT"],
+    ["SAME", "ÉGALE"],
+    ["BY DISTANCE", "SELON DISTANCE"],
+    ["ENGLISH", "ENGLISH"],
+    ["FRANÇAIS", "FRANÇAIS"],
+    ["DEUTERANOPIA", "DEUTÉRANOPIE"],
+    ["PROTANOPIA", "PROTANOPIE"],
+    ["TRITANOPIA", "TRITANOPIE"],
+];
+
+impl Id {
+
+    pub fn text(self, language: Language) -> &'static str {
+        STRINGS[self as usize][language as usize]
+    }
+
+}
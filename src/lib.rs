@@ -5,18 +5,142 @@
 #![cfg_attr(test, test_runner(agb::test_runner::test_runner))]
 
 
-use agb::{display::{object::{Object, Sprite, SpriteVram}, tiled::{RegularBackground, RegularBackgroundSize, TileFormat, VRAM_MANAGER}, Graphics, Priority}, fixnum::Vector2D, include_aseprite, include_background_gfx, input::{Button, ButtonController}, rng::RandomNumberGenerator};
+use agb::{display::{object::{GraphicsMode, Object, Sprite, SpriteVram}, tiled::{RegularBackground, RegularBackgroundId}, GraphicsFrame}, fixnum::{num, Vector2D}, include_background_gfx, input::{Button, ButtonController}, rng::RandomNumberGenerator, sound::mixer::Frequency};
 use alloc::vec::Vec;
 use alloc::vec;
 
-use crate::graphics::{game_sprite_init, value_to_sprite_index, START_ANIMATION};
-use crate::logic::Direction;
+use crate::graphics::{game_sprite_init, value_to_sprite_index, Anim, Layout, VERSUS_LAYOUTS};
+use crate::link::{Message, Transport};
+use crate::input::Input;
+use crate::lang::{Id, Language};
+use crate::popup::Popups;
+use crate::tween::Tweens;
+use crate::scene::{Context, Scene, Scenes};
+use crate::settings::{Bindings, Curve, Settings, Spawns, Speed, Theme, Vision, MAX_HINTS, MAX_QUEUE, MAX_UNDO, MAX_VOLUME};
+use crate::logic::{Bombs, Cell, Danger, Direction, Jokers, Modifiers, Obstacles, Ruleset, Snapshot, BEND, FIVE, LAYERS, PLUS, RING, SQUARE};
 pub mod graphics;
+mod hud;
+pub mod link;
 pub mod logic;
+mod input;
+mod lang;
+mod music;
+mod popup;
+mod scene;
+mod sfx;
+mod text;
+mod theme;
+mod tween;
+pub mod settings;
 extern crate alloc;
 
-include_background_gfx!(mod background, bg => deduplicate "gfx/bg.aseprite");
-include_aseprite!(mod tile_gfx, "gfx/tiles.aseprite", "gfx/tiles_menu.aseprite", "gfx/buttons.aseprite");
+include_background_gfx!(mod background, bg => deduplicate "gfx/bg.aseprite", bg_versus => deduplicate "gfx/bg_versus.aseprite", bg_plain => deduplicate "gfx/bg_plain.aseprite", font => "gfx/font.aseprite");
+//include_aseprite's contents built on theme's sprites, which are recoloured to the theme as they're loaded
+mod tile_gfx {
+    use agb::align_bytes;
+    use agb::display::object::Size;
+    use agb::display::{Palette16, Rgb15};
+    use crate::theme::{Sprite, Tag};
+
+    agb::include_aseprite_inner!("gfx/tiles.aseprite", "gfx/tiles_threes.aseprite", "gfx/tiles_small.aseprite", "gfx/tiles_special.aseprite", "gfx/tiles_menu.aseprite", "gfx/buttons.aseprite", "gfx/popup.aseprite", "gfx/marks.aseprite");
+}
+
+//walls used by the maze layout
+const MAZE: &[usize] = &[5, 10];
+
+//game modes selectable from the title screen with L and R
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Classic,
+    Threes,
+    Maze, //fixed walls in the middle of the board
+    Walls, //survival, a new wall rises every few moves
+    Wild, //jokers occasionally spawn in place of numbers
+    Wrap, //the board wraps around at the edges
+    Deep, //two layers, L and R move tiles between them
+    Custom, //rules picked on the setup screen
+    Duel, //two players taking turns
+    Race, //two players against the clock
+    Link, //two consoles over the link cable, big merges send garbage
+}
+
+impl Mode {
+
+    fn next(self) -> Self {
+        match self {
+            Mode::Classic => Mode::Threes,
+            Mode::Threes => Mode::Maze,
+            Mode::Maze => Mode::Walls,
+            Mode::Walls => Mode::Wild,
+            Mode::Wild => Mode::Wrap,
+            Mode::Wrap => Mode::Deep,
+            Mode::Deep => Mode::Custom,
+            Mode::Custom => Mode::Duel,
+            Mode::Duel => Mode::Race,
+            Mode::Race => Mode::Link,
+            Mode::Link => Mode::Classic,
+        }
+    }
+
+    fn previous(self) -> Self {
+        match self {
+            Mode::Classic => Mode::Link,
+            Mode::Threes => Mode::Classic,
+            Mode::Maze => Mode::Threes,
+            Mode::Walls => Mode::Maze,
+            Mode::Wild => Mode::Walls,
+            Mode::Wrap => Mode::Wild,
+            Mode::Deep => Mode::Wrap,
+            Mode::Custom => Mode::Deep,
+            Mode::Duel => Mode::Custom,
+            Mode::Race => Mode::Duel,
+            Mode::Link => Mode::Race,
+        }
+    }
+
+    fn name(self) -> Id {
+        match self {
+            Mode::Classic => Id::Classic,
+            Mode::Threes => Id::Threes,
+            Mode::Maze => Id::Maze,
+            Mode::Walls => Id::Walls,
+            Mode::Wild => Id::Wild,
+            Mode::Wrap => Id::Wrap,
+            Mode::Deep => Id::Deep,
+            Mode::Custom => Id::Custom,
+            Mode::Duel => Id::Duel,
+            Mode::Race => Id::Race,
+            Mode::Link => Id::Link,
+        }
+    }
+
+    fn contest(self) -> Option<Contest> {
+        match self {
+            Mode::Duel => Some(Contest::Turns),
+            Mode::Race => Some(Contest::Race),
+            _ => None,
+        }
+    }
+
+    fn ruleset(self) -> Ruleset {
+        match self {
+            Mode::Threes => Ruleset::Threes,
+            _ => Ruleset::Classic,
+        }
+    }
+
+    fn modifiers(self) -> Modifiers {
+        match self {
+            Mode::Maze => Modifiers { obstacles: Obstacles::Fixed(MAZE), ..Default::default() },
+            Mode::Walls => Modifiers { obstacles: Obstacles::Survival(10), ..Default::default() },
+            Mode::Wild => Modifiers { jokers: Jokers::Chance(5), ..Default::default() },
+            Mode::Wrap => Modifiers { wrap: true, ..Default::default() },
+            Mode::Deep => Modifiers { shape: LAYERS, ..Default::default() },
+            _ => Modifiers::default(),
+        }
+    }
+
+}
 
 #[derive(Debug, Clone)]
 struct Menu {
@@ -25,9 +149,10 @@ struct Menu {
     button: Object,
     //sprites: Vec<SpriteVram>,
     test: bool,
+    mode: Mode,
     game_over: bool,
-    pressed: bool,
     high_score: u32,
+    last_score: u32, //of the game that just ended
 }
 
 impl Menu {
@@ -49,9 +174,10 @@ impl Menu {
             ],
             button: Object::new(SpriteVram::from(tile_gfx::A.sprite(0))),
             test: false,
+            mode: Mode::Classic,
             game_over: false,
-            pressed: false,
             high_score: 0,
+            last_score: 0,
         }
     }
 
@@ -59,243 +185,740 @@ impl Menu {
 }
 
 
+//custom rules screen, one row per rule with an icon and the chosen option
+const SETUP_ROWS: usize = 5;
+
 #[derive(Debug, Clone)]
-struct Tile {
-    object: Object,             
-    pos: Vector2D<i32>,
-    value: u16,
-    update_obj: bool, //true when object sprite needs updating
-    animate: Option<Vector2D<i32>>, //Some(destination) when animation needed
-    appearing: bool, //true when tile is queued to appear
+struct Setup {
+    icons: [Object; SETUP_ROWS],
+    cursor: usize,
+    ruleset: Ruleset,
+    modifiers: Modifiers,
 }
 
-impl PartialOrd for Tile {
-    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
-        self.pos.y.partial_cmp(&other.pos.y) //prioritize comparing the y value
+impl Setup {
+
+    fn new() -> Self {
+
+        Self {
+            icons: [
+                Object::new(SpriteVram::from(tile_gfx::TWO.sprite(0))),
+                Object::new(SpriteVram::from(tile_gfx::OBSTACLE.sprite(0))),
+                Object::new(SpriteVram::from(tile_gfx::JOKER.sprite(0))),
+                Object::new(SpriteVram::from(tile_gfx::BOMB.sprite(0))),
+                Object::new(SpriteVram::from(tile_gfx::CELL.sprite(0))),
+            ],
+            cursor: 0,
+            ruleset: Ruleset::Classic,
+            modifiers: Modifiers::default(),
+        }
+
     }
+
+    //steps the option on the selected row forwards or backwards
+    fn change(&mut self, forward: bool) {
+
+        match self.cursor {
+            0 => {
+                self.ruleset = match self.ruleset {
+                    Ruleset::Classic => Ruleset::Threes,
+                    Ruleset::Threes => Ruleset::Classic,
+                };
+            },
+            1 => {
+                let options = [Obstacles::None, Obstacles::Fixed(MAZE), Obstacles::Survival(10)];
+                self.modifiers.obstacles = cycle(&options, self.modifiers.obstacles, forward);
+            },
+            2 => {
+                let options = [Jokers::None, Jokers::Chance(5), Jokers::Reward];
+                self.modifiers.jokers = cycle(&options, self.modifiers.jokers, forward);
+            },
+            3 => {
+                let options = [Bombs::None, Bombs::Row, Bombs::Column, Bombs::Square];
+                self.modifiers.bombs = cycle(&options, self.modifiers.bombs, forward);
+            },
+            _ => {
+                let options = [SQUARE, FIVE, PLUS, BEND, RING];
+                self.modifiers.shape = cycle(&options, self.modifiers.shape, forward);
+            },
+        }
+
+    }
+
+    //one frame of input, Some(true) once a game should start, Some(false) to go back to the title
+    fn update(&mut self, input: &ButtonController, settings: &Settings) -> Option<bool> {
+
+        if input.is_just_pressed(Button::UP) {
+            self.cursor = (self.cursor + SETUP_ROWS - 1) % SETUP_ROWS;
+        } else if input.is_just_pressed(Button::DOWN) {
+            self.cursor = (self.cursor + 1) % SETUP_ROWS;
+        } else if input.is_just_pressed(Button::LEFT) {
+            self.change(false);
+        } else if input.is_just_pressed(Button::RIGHT) {
+            self.change(true);
+        }
+
+        if input.is_just_pressed(settings.confirm()) {
+            Some(true)
+        } else if input.is_just_pressed(settings.back()) {
+            Some(false)
+        } else {
+            None
+        }
+
+    }
+
 }
 
-impl Ord for Tile {
-    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
-        self.pos.y.cmp(&other.pos.y).then(self.pos.x.cmp(&other.pos.x)) //compare y value before x
+//the option after (or before) current in options, wrapping around
+fn cycle<T: Copy + PartialEq>(options: &[T], current: T, forward: bool) -> T {
+
+    let i = options.iter().position(|o| *o == current).unwrap_or(0);
+
+    if forward {
+        options[(i + 1) % options.len()]
+    } else {
+        options[(i + options.len() - 1) % options.len()]
     }
+
 }
 
-impl PartialEq for Tile {
-    fn eq(&self, other: &Self) -> bool {
-        self.value == other.value
+//entries in the pause menu, in the order UP and DOWN step through them
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PauseItem {
+    Resume,
+    Restart,
+    Undo,
+    Options,
+    Quit, //back to the title, recording the game like a game over would
+}
+
+impl PauseItem {
+
+    fn name(self) -> Id {
+        match self {
+            PauseItem::Resume => Id::Resume,
+            PauseItem::Restart => Id::Restart,
+            PauseItem::Undo => Id::Undo,
+            PauseItem::Options => Id::Options,
+            PauseItem::Quit => Id::Quit,
+        }
     }
+
 }
 
-impl Eq for Tile {}
+const PAUSE_ITEMS: [PauseItem; 5] = [PauseItem::Resume, PauseItem::Restart, PauseItem::Undo, PauseItem::Options, PauseItem::Quit];
 
-#[derive(Debug)]
-struct Game {
-    board: Vec<Tile>,
-    sprites: Vec<SpriteVram>,
-    score: u32,
-    spawn: bool,
+//overlay opened with START, only the selected entry is shown
+#[derive(Debug, Clone)]
+struct Pause {
+    cursor: usize,
 }
 
-impl Game {
+impl Pause {
 
     fn new() -> Self {
+        Self { cursor: 0 }
+    }
 
-        let mut tiles = vec![];
+    fn selected(&self) -> PauseItem {
+        PAUSE_ITEMS[self.cursor]
+    }
 
-        let sprites = game_sprite_init();
-
-        for y in 0..4 {
-            for x in 0..4 {
-                tiles.push(Tile {
-                    object: Object::new(sprites[0].clone()),
-                    pos: (x, y).into(),
-                    value: 0,
-                    update_obj: false,
-                    animate: None,
-                    appearing: false,
-                });
+    //one frame of input, available says which entries can be picked right now and the rest are skipped over
+    fn update(&mut self, input: &ButtonController, settings: &Settings, available: impl Fn(PauseItem) -> bool) -> Option<PauseItem> {
+
+        let step = if input.is_just_pressed(Button::UP) {
+            PAUSE_ITEMS.len() - 1
+        } else if input.is_just_pressed(Button::DOWN) {
+            1
+        } else {
+            0
+        };
+
+        if step != 0 {
+            //resume is always available so this can't go round forever
+            loop {
+                self.cursor = (self.cursor + step) % PAUSE_ITEMS.len();
+                if available(self.selected()) {
+                    break;
+                }
             }
         }
 
-        for tile in &mut tiles {
-            tile.set_pos();
+        if input.is_just_pressed(Button::START) || input.is_just_pressed(settings.back()) {
+            Some(PauseItem::Resume)
+        } else if input.is_just_pressed(settings.confirm()) {
+            Some(self.selected())
+        } else {
+            None
         }
 
-        Self { board: tiles, sprites: sprites, score: 0, spawn: true }
     }
 
-    fn init_with_board(values: [u16; 16]) -> Self {
-
-        let mut game = Self::new();
+}
 
-        for (i, value) in values.iter().enumerate() {
+//the options screen, one setting at a time with its name above its value
+const OPTION_ROWS: usize = 16;
 
-            let sprite = game.sprites[value_to_sprite_index(*value).unwrap_or(0)].clone();
+#[derive(Debug, Clone)]
+struct Options {
+    cursor: usize,
+}
 
-            game.board[i].value = *value;
-            game.board[i].object = Object::new(sprite);
+impl Options {
 
+    fn new() -> Self {
+        Self { cursor: 0 }
+    }
 
+    //steps the setting under the cursor forwards or backwards
+    fn change(&self, settings: &mut Settings, forward: bool) {
+
+        //numbers run from 0 to max and wrap at either end
+        let step = |value: u8, max: u8| if forward { (value + 1) % (max + 1) } else { (value + max) % (max + 1) };
+
+        match self.cursor {
+            0 => settings.speed = cycle(&[Speed::Slow, Speed::Normal, Speed::Fast], settings.speed, forward),
+            1 => settings.music_volume = step(settings.music_volume, MAX_VOLUME),
+            2 => settings.sfx_volume = step(settings.sfx_volume, MAX_VOLUME),
+            3 => settings.theme = cycle(&[Theme::Classic, Theme::Dark, Theme::Contrast, Theme::Pastel, Theme::Green], settings.theme, forward),
+            4 => settings.spawns = cycle(&[Spawns::Stingy, Spawns::Classic, Spawns::Generous], settings.spawns, forward),
+            5 => settings.undo_depth = step(settings.undo_depth, MAX_UNDO),
+            6 => settings.hints = step(settings.hints, MAX_HINTS),
+            7 => settings.bindings = cycle(&[Bindings::Standard, Bindings::Swapped], settings.bindings, forward),
+            8 => settings.reduced_motion = !settings.reduced_motion,
+            9 => settings.queue_depth = step(settings.queue_depth, MAX_QUEUE),
+            10 => settings.skip_animations = !settings.skip_animations,
+            11 => settings.curve = cycle(&[Curve::Linear, Curve::Ease, Curve::Back], settings.curve, forward),
+            12 => settings.slide_by_distance = !settings.slide_by_distance,
+            13 => settings.language = cycle(&[Language::English, Language::French], settings.language, forward),
+            14 => {
+                let options = [Vision::Normal, Vision::Deuteranopia, Vision::Protanopia, Vision::Tritanopia];
+                settings.vision = cycle(&options, settings.vision, forward);
+            },
+            _ => settings.patterns = !settings.patterns,
         }
 
-        game.spawn = false;
+    }
 
-        game
+    //one frame of input, returns true once the screen should close
+    fn update(&mut self, input: &ButtonController, settings: &mut Settings) -> bool {
+
+        //read before anything changes so swapping the bindings doesn't close the screen straight away
+        let back = input.is_just_pressed(settings.back()) || input.is_just_pressed(Button::START);
+
+        if input.is_just_pressed(Button::UP) {
+            self.cursor = (self.cursor + OPTION_ROWS - 1) % OPTION_ROWS;
+        } else if input.is_just_pressed(Button::DOWN) {
+            self.cursor = (self.cursor + 1) % OPTION_ROWS;
+        } else if input.is_just_pressed(Button::LEFT) {
+            self.change(settings, false);
+        } else if input.is_just_pressed(Button::RIGHT) {
+            self.change(settings, true);
+        } else {
+            return back;
+        }
+
+        false
 
     }
 
-    fn init(mut rng: &mut RandomNumberGenerator) -> Self {
+}
 
-        let mut game = Self::new();
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Contest {
+    Turns, //players alternate moves, the first one left without a move loses
+    Race, //each player gets RACE_TIME frames on their own board, highest score wins
+}
 
-        game.spawn_tile(&mut rng);
-        game.spawn_tile(&mut rng);
+const RACE_TIME: u32 = 60 * 60;
 
-        game
+//two boards side by side sharing one console
+struct Versus {
+    games: [Game; 2],
+    rngs: [RandomNumberGenerator; 2], //each board gets its own seed so spawns don't depend on the other player
+    turn: usize,
+    contest: Contest,
+    clock: u32, //frames used by the current player in a race
+}
+
+impl Versus {
+
+    fn init(rng: &mut RandomNumberGenerator, contest: Contest, settings: Settings) -> Self {
+
+        let mut rngs: [RandomNumberGenerator; 2] = core::array::from_fn(|_| {
+            RandomNumberGenerator::new_with_seed([rng.next_i32() as u32, rng.next_i32() as u32, rng.next_i32() as u32, rng.next_i32() as u32])
+        });
+
+        let games = core::array::from_fn(|side| {
+            let mut game = Game::init(&mut rngs[side], Ruleset::Classic, Modifiers::default(), settings);
+            game.set_layout(VERSUS_LAYOUTS[side]);
+            game.set_dimmed(side != 0);
+            game
+        });
+
+        Self { games, rngs, turn: 0, contest, clock: 0 }
 
     }
 
-    fn play(&mut self, input: &mut ButtonController, gfx: &mut Graphics, rng: &mut RandomNumberGenerator, bg: &RegularBackground) {
+    fn switch_turn(&mut self) {
+        self.games[self.turn].set_dimmed(true);
+        self.turn = 1 - self.turn;
+        self.games[self.turn].set_dimmed(false);
+    }
+
+    //one frame of play, returns Some(winner) once the match is decided, with None as the winner for a draw
+    fn update(&mut self, input: &mut Input) -> Option<Option<usize>> {
 
-        loop {
+        for game in self.games.iter_mut() {
+            game.animate();
+        }
 
+        if let Some(dir) = self.games[self.turn].take_move(input) {
 
-            input.update();
+            let moved = self.games[self.turn].shift(dir, &mut self.rngs[self.turn]);
 
-            //TODO: improve input system
-            if input.is_just_pressed(Button::UP) {
-                self.shift(Direction::Up, rng, gfx, &bg);
-            } else if input.is_just_pressed(Button::DOWN) {
-                self.shift(Direction::Down, rng, gfx, &bg);
-            } else if input.is_just_pressed(Button::LEFT) {
-                self.shift(Direction::Left, rng, gfx, &bg);
-            } else if input.is_just_pressed(Button::RIGHT) {
-                self.shift(Direction::Right, rng, gfx, &bg);
+            //moves pressed ahead were meant for the board that just moved, not the next player's
+            if moved && self.contest == Contest::Turns {
+                self.switch_turn();
+                input.clear();
             }
-    
-            if self.spawn == false {
-                if input.is_just_pressed(Button::B) {
-                    self.spawn_tile(rng);
+
+        }
+
+        let result = match self.contest {
+            Contest::Turns => {
+                if self.games[self.turn].check_stuck() { Some(Some(1 - self.turn)) } else { None }
+            },
+            Contest::Race => {
+                self.clock += 1;
+
+                if self.clock < RACE_TIME && !self.games[self.turn].check_stuck() {
+                    None
+                } else if self.turn == 0 {
+                    self.switch_turn();
+                    self.clock = 0;
+                    None
+                } else {
+                    Some(match self.games[0].score.cmp(&self.games[1].score) {
+                        core::cmp::Ordering::Greater => Some(0),
+                        core::cmp::Ordering::Less => Some(1),
+                        core::cmp::Ordering::Equal => None,
+                    })
                 }
-            }
+            },
+        };
 
-            if self.check_stuck() {
-                break;
+        //leave only the winner lit
+        if let Some(winner) = result {
+            for (side, game) in self.games.iter_mut().enumerate() {
+                game.set_dimmed(winner.is_some_and(|w| w != side));
             }
-
-            let mut frame = gfx.frame();
-    
-            bg.show(&mut frame);
-            self.show_tiles(&mut frame);
-            
-    
-            frame.commit();
         }
 
+        result
+
     }
 
 }
 
+//two consoles, each player sees their own board on the left
+struct Link<T: Transport> {
+    transport: T,
+    games: [Game; 2], //local then remote, the remote board is replayed from the moves it sends
+    rngs: [RandomNumberGenerator; 2],
+    garbage: u8, //attacks received but not dropped yet, they land after our next move
+}
 
+impl<T: Transport> Link<T> {
 
+    //the host makes up the seed and sends it, then waits for it to come back so it knows the other side has it.
+    //the other side sends it back as soon as it arrives. sent remembers the host's seed between frames
+    fn handshake(transport: &mut T, sent: &mut Option<u32>, rng: &mut RandomNumberGenerator) -> Option<u32> {
 
-pub fn run(mut gba: agb::Gba) -> ! {
+        if transport.is_host() {
+            let seed = *sent.get_or_insert_with(|| {
+                let seed = rng.next_i32() as u32;
+                transport.send(Message::Seed(seed));
+                seed
+            });
 
-    let mut gfx = gba.graphics.get();
-    let mut rng = RandomNumberGenerator::new();
-    let mut input = ButtonController::new();
+            match transport.receive() {
+                Some(Message::Seed(echo)) if echo == seed => Some(seed),
+                _ => None,
+            }
+        } else {
+            //a console that was host until the cable went in starts over as the other side
+            *sent = None;
+
+            match transport.receive() {
+                Some(Message::Seed(seed)) => {
+                    transport.send(Message::Seed(seed));
+                    Some(seed)
+                },
+                _ => None,
+            }
+        }
 
-    VRAM_MANAGER.set_background_palettes(background::PALETTES);
+    }
 
-    let mut bg = RegularBackground::new(Priority::P0, RegularBackgroundSize::Background32x32, TileFormat::FourBpp);
-    bg.fill_with(&background::bg);
+    fn new(transport: T, seed: u32, settings: Settings) -> Self {
 
-    let mut menu = Menu::new();
-    menu.set();
+        //the host's board always uses the first rng so both consoles replay the same spawns
+        let sides: [u32; 2] = if transport.is_host() { [0, 1] } else { [1, 0] };
+        let mut rngs = sides.map(|side| RandomNumberGenerator::new_with_seed([seed, side, seed.rotate_left(16), 0x2048]));
 
+        //the other console's settings aren't known, so spawns stick to the classic odds on both
+        let settings = Settings { spawns: Spawns::Classic, ..settings };
 
-    loop {
+        let games = core::array::from_fn(|i| {
+            let mut game = Game::init(&mut rngs[i], Ruleset::Classic, Modifiers::default(), settings);
+            game.set_layout(VERSUS_LAYOUTS[i]);
+            game.set_dimmed(i != 0);
+            game
+        });
+
+        Self { transport, games, rngs, garbage: 0 }
+
+    }
+
+    //one frame of play, returns Some(0) if we won, Some(1) if the other console did
+    fn update(&mut self, input: &mut Input) -> Option<usize> {
+
+        self.transport.update();
+
+        for game in self.games.iter_mut() {
+            game.animate();
+        }
 
-        menu.game_over = false;
-        menu.pressed = false;
+        let mut winner = self.listen();
 
-        while !menu.pressed {
+        if winner.is_none() {
 
-            rng.next_i32(); //call every frame to randomize rng state
-            let mut frame = gfx.frame();
+            if let Some(dir) = self.games[0].take_move(input) {
+                self.play(dir);
+            }
 
-            bg.show(&mut frame);
-            menu.show(&mut frame);
+            if self.games[0].check_stuck() {
+                self.transport.send(Message::GameOver { score: self.games[0].score });
+                winner = Some(1);
+            }
+
+        }
 
-            frame.commit();
+        if let Some(winner) = winner {
+            self.games[0].set_dimmed(winner != 0);
+            self.games[1].set_dimmed(winner != 1);
+        }
+
+        winner
+
+    }
 
+    //replays whatever the other console has sent, Some(0) if it's stuck
+    fn listen(&mut self) -> Option<usize> {
 
-            input.update();
+        let mut winner = None;
 
-            //CHECK FOR PRESS
+        while let Some(message) = self.transport.receive() {
+            match message {
+                Message::Move { dir, garbage } => {
+                    let remote = &mut self.games[1];
 
-            if input.is_pressed(Button::SELECT) {
-                menu.test(true);
-            } else {
-                menu.test(false);
+                    remote.shift(dir, &mut self.rngs[1]);
+                    remote.drop_garbage(garbage, &mut self.rngs[1]);
+                },
+                Message::Attack(count) => self.garbage = self.garbage.saturating_add(count),
+                Message::GameOver { .. } => winner = Some(0),
+                Message::Seed(_) => {},
             }
+        }
+
+        winner
+
+    }
+
+    //makes a move on our board and tells the other console about it, returns true if anything moved
+    fn play(&mut self, dir: Direction) -> bool {
+
+        let local = &mut self.games[0];
+
+        let moved = local.shift(dir, &mut self.rngs[0]);
+
+        if moved {
+            local.drop_garbage(self.garbage, &mut self.rngs[0]);
+            self.transport.send(Message::Move { dir, garbage: self.garbage });
+            self.garbage = 0;
 
-            if input.is_just_pressed(Button::A) {
-                menu.animate(&mut gfx, &bg);
-                menu.pressed = true;
+            if local.attack > 0 {
+                self.transport.send(Message::Attack(local.attack));
             }
+        }
+
+        moved
+
+    }
+
+}
+
+//side by side boards, dimmed ones are drawn see-through
+fn show_boards(games: &mut [Game], frame: &mut GraphicsFrame, bg: &RegularBackground, hud: RegularBackgroundId) -> Vec<RegularBackgroundId> {
+
+    let bg_id = bg.show(frame);
+
+    for game in games.iter_mut() {
+        game.show_tiles(frame);
+    }
+
+    frame.blend().object_transparency(num!(0.5), num!(0.5)).enable_background(bg_id);
+
+    vec![hud, bg_id]
+
+}
+
+
+//how a tile the move made shows up once the slide has landed
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Appearing {
+    Moved, //just appears where it stopped
+    Merged, //pops up past full size and settles back
+    Spawned, //grows out of nothing
+}
+
+//something that happened on a board that the player should hear about, collected until the scene takes them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Slide,
+    Merge(u16), //once the slide lands, the highest value made
+    Spawn,
+    Blocked, //a move that didn't move anything
+    Target, //the ruleset's target tile made for the first time
+}
+
+#[derive(Debug, Clone)]
+struct Tile {
+    object: Object,             
+    pos: Vector2D<i32>,
+    cell: Cell,
+    update_obj: bool, //true when object sprite needs updating
+    animate: Option<Vector2D<i32>>, //Some(destination) when animation needed
+    appearing: Option<Appearing>, //Some when the tile is queued to appear, and how it arrives
+    exploding: bool, //true when tile was cleared by a bomb and the blast still needs drawing
+    mark: Option<Object>, //pattern drawn over the tile when those are turned on
+}
+
+impl PartialOrd for Tile {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        self.pos.y.partial_cmp(&other.pos.y) //prioritize comparing the y value
+    }
+}
+
+impl Ord for Tile {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.pos.y.cmp(&other.pos.y).then(self.pos.x.cmp(&other.pos.x)) //compare y value before x
+    }
+}
+
+impl PartialEq for Tile {
+    fn eq(&self, other: &Self) -> bool {
+        self.cell == other.cell
+    }
+}
+
+impl Eq for Tile {}
+
+#[derive(Debug)]
+struct Game {
+    board: Vec<Tile>,
+    sprites: Vec<SpriteVram>,
+    score: u32,
+    spawn: bool,
+    rules: Ruleset,
+    modifiers: Modifiers,
+    moves: u32,
+    best_tile: u16, //highest value merged so far
+    next: Cell, //the next tile to spawn
+    blasts: Vec<usize>, //bombs set off by the last move, waiting to explode
+    attack: u8, //garbage earned by the last move in link battles
+    layout: Layout,
+    backdrop: Vec<Object>, //empty cells of irregular boards
+    preview: Object, //next tile indicator, only shown for threes
+    history: Vec<Snapshot>, //boards before the last few moves, newest last
+    settings: Settings,
+    hints: u8, //left to spend this game
+    hint: Option<Direction>, //the move suggested by the last hint, until a move is made
+    tweens: Tweens<Anim>,
+    mode: GraphicsMode, //see-through when dimmed, kept for tiles drawn with fresh objects
+    gains: Vec<(usize, u16)>, //cells merged by the last move and what they're worth, popped up once it lands
+    popups: Popups,
+    shown_score: u32, //what the hud shows, counts up to score
+    time: u32, //frames played
+    events: Vec<Event>,
+    danger: Danger,
+}
+
+impl Game {
+
+    fn new(rules: Ruleset, modifiers: Modifiers, settings: Settings) -> Self {
+
+        let mut tiles = vec![];
+
+        let shape = modifiers.shape;
+        let layout = Layout::fit(shape);
+        let sprites = game_sprite_init(rules, layout);
+
+        for i in 0..shape.area() {
+
+            //layers sit side by side on screen
+            let (x, y, z) = shape.coords(i);
+
+            tiles.push(Tile {
+                object: Object::new(sprites[0].clone()),
+                pos: ((z * shape.width + x) as i32, y as i32).into(),
+                cell: if shape.holes.contains(&i) { Cell::Hole } else { Cell::Empty },
+                update_obj: false,
+                animate: None,
+                appearing: None,
+                exploding: false,
+                mark: None,
+            });
 
         }
 
-        if menu.test {
+        for tile in &mut tiles {
+            tile.set_pos(layout);
+        }
 
-            let tile_values = [
-                8, 16, 8, 16,
-                16, 8, 16, 8,
-                8, 16, 8, 16,
-                16, 0, 16, 8,
-            ];
+        let preview = Object::new(sprites[0].clone());
 
-            let mut game = Game::init_with_board(tile_values);
+        let mut game = Self { board: tiles, sprites: sprites, score: 0, spawn: true, rules, modifiers, moves: 0, best_tile: 0, next: Cell::Number(2), blasts: vec![], attack: 0, layout, backdrop: vec![], preview, history: vec![], settings, hints: settings.hints, hint: None, tweens: Tweens::new(), mode: GraphicsMode::Normal, gains: vec![], popups: Popups::new(), shown_score: 0, time: 0, events: vec![], danger: Danger::default() };
+        game.build_backdrop();
+        game
+    }
 
-            game.play(&mut input, &mut gfx, &mut rng, &bg);
+    fn init_with_board(values: [u16; 16], settings: Settings) -> Self {
 
-        } else {
+        let mut game = Self::new(Ruleset::Classic, Modifiers::default(), settings);
+
+        for (i, value) in values.iter().enumerate() {
 
-            let mut game = Game::init(&mut rng);
+            let index = value_to_sprite_index(*value, game.rules).unwrap_or(0).min(game.sprites.len() - 1);
+            let sprite = game.sprites[index].clone();
+
+            game.board[i].cell = Cell::from(*value);
+            game.board[i].object = Object::new(sprite);
 
-            game.play(&mut input, &mut gfx, &mut rng, &bg);
 
         }
 
-        menu.pressed = false;
-        menu.game_over = true;
-        menu.set();
-        menu.fade_out(&mut gfx, &bg, START_ANIMATION * 4);
-        menu.fade_in(&mut gfx, &bg, START_ANIMATION);
+        game.spawn = false;
+        game.assess();
+
+        game
+
+    }
+
+    fn init(mut rng: &mut RandomNumberGenerator, rules: Ruleset, modifiers: Modifiers, settings: Settings) -> Self {
 
-        while !menu.pressed {
+        let mut game = Self::new(rules, modifiers, settings);
 
-            rng.next_i32(); //call every frame to randomize rng state
-            let mut frame = gfx.frame();
+        game.place_obstacles();
+        game.roll_next(rng);
 
-            bg.show(&mut frame);
-            menu.show(&mut frame);
+        let starting_tiles = match rules {
+            Ruleset::Classic => 2,
+            Ruleset::Threes => 6,
+        };
 
-            frame.commit();
+        for _ in 0..starting_tiles {
+            game.spawn_tile(&mut rng);
+        }
+
+        game.assess();
 
+        game
 
-            input.update();
+    }
 
-            if input.is_just_pressed(Button::A) {
-                menu.fade_in_out(&mut gfx, &bg, START_ANIMATION / 2);
-                menu.pressed = true;
+    //the move to make this frame, presses made while tiles are still moving wait in the input queue
+    fn take_move(&mut self, input: &mut Input) -> Option<Direction> {
+
+        if self.is_animating() {
+            if !input.hold() {
+                return None;
+            }
+            self.finish_animations();
+        }
+
+        input.next_move()
+
+    }
+
+    //one frame of play, returns true once there are no moves left
+    fn update(&mut self, input: &mut Input, rng: &mut RandomNumberGenerator) -> bool {
+
+        self.time += 1;
+        self.animate();
+
+        if let Some(dir) = self.take_move(input) {
+            self.shift(dir, rng);
+        }
+
+        if self.spawn == false {
+            if input.buttons.is_just_pressed(Button::B) {
+                self.spawn_tile(rng);
             }
+        }
 
+        if input.buttons.is_just_pressed(Button::SELECT) && self.hint.is_none() && self.hints > 0 {
+            self.hint = self.best_move();
+            if self.hint.is_some() {
+                self.hints -= 1;
+            }
         }
 
+        self.check_stuck()
+
+    }
+
+    //the hud has to have been shown already so it's clipped along with the board
+    fn show(&mut self, frame: &mut GraphicsFrame, bg: &RegularBackground, hud: RegularBackgroundId) -> Vec<RegularBackgroundId> {
 
+        let layers = vec![hud, bg.show(frame)];
+        self.show_tiles(frame);
+        self.show_preview(frame);
+        self.clip(frame, &layers);
+
+        layers
+
+    }
+
+}
+
+//tests run in place of the game, the entry macro hands over to the test runner
+#[cfg(test)]
+#[agb::entry]
+fn agb_test_main(_gba: agb::Gba) -> ! {
+    loop {
+        agb::halt();
     }
 }
 
+pub fn run(mut gba: agb::Gba) -> ! {
+
+    let mut gfx = gba.graphics.get();
+    let mut mixer = gba.mixer.mixer(Frequency::Hz32768); //the only rate tracker music plays at
+
+    let mut ctx = Context::new(gba.save);
+    let mut scenes = Scenes::new(Scene::Title, &mut ctx);
+
+    loop {
+        scenes.update(&mut ctx);
+        ctx.music.update(&mut mixer, &ctx.settings);
+        ctx.sounds.update(&mut mixer, &ctx.settings);
+        mixer.frame();
+        scenes.render(&mut ctx, &mut gfx);
+    }
+
+}
@@ -0,0 +1,386 @@
+use core::ptr::{read_volatile, write_volatile};
+extern crate alloc;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::logic::Direction;
+
+
+//everything the two consoles say to each other during a link battle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Message {
+    Seed(u32), //sent by the host, both boards are seeded from it so each side can replay the other's moves
+    Move { dir: Direction, garbage: u8 }, //a move that changed the board, and how many garbage tiles dropped after it
+    Attack(u8), //garbage tiles for the opponent, queued until their next move
+    GameOver { score: u32 }, //the sender is stuck
+}
+
+impl Message {
+
+    //bytes on the wire: a kind byte then the payload
+    fn encode(&self, out: &mut VecDeque<u8>) {
+
+        match *self {
+            Message::Seed(seed) => {
+                out.push_back(1);
+                out.extend(seed.to_le_bytes());
+            },
+            Message::Move { dir, garbage } => {
+                out.push_back(2);
+                out.push_back(dir as u8);
+                out.push_back(garbage);
+            },
+            Message::Attack(count) => {
+                out.push_back(3);
+                out.push_back(count);
+            },
+            Message::GameOver { score } => {
+                out.push_back(4);
+                out.extend(score.to_le_bytes());
+            },
+        }
+
+    }
+
+    //takes a whole message off the front of the buffer, or leaves it alone if one hasn't fully arrived yet
+    fn decode(buffer: &mut VecDeque<u8>) -> Option<Self> {
+
+        let length = match buffer.front()? {
+            1 | 4 => 5,
+            2 => 3,
+            3 => 2,
+            _ => {
+                //garbled byte, drop it and resync on the next one
+                buffer.pop_front();
+                return None;
+            },
+        };
+
+        if buffer.len() < length {
+            return None;
+        }
+
+        let bytes: alloc::vec::Vec<u8> = buffer.drain(..length).collect();
+        let word = || u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+
+        match bytes[0] {
+            1 => Some(Message::Seed(word())),
+            2 => {
+                let dir = match bytes[1] {
+                    0 => Direction::Up,
+                    1 => Direction::Down,
+                    2 => Direction::Left,
+                    3 => Direction::Right,
+                    4 => Direction::In,
+                    _ => Direction::Out,
+                };
+                Some(Message::Move { dir, garbage: bytes[2] })
+            },
+            3 => Some(Message::Attack(bytes[1])),
+            _ => Some(Message::GameOver { score: word() }),
+        }
+
+    }
+
+}
+
+pub trait Transport {
+
+    //called once per frame to move bytes along
+    fn update(&mut self);
+
+    fn send(&mut self, message: Message);
+
+    fn receive(&mut self) -> Option<Message>;
+
+    //the host picks the seed and plays the left hand board's rng
+    fn is_host(&self) -> bool;
+
+}
+
+
+//both ends of a link in memory, so battles can be simulated without two consoles
+pub struct Loopback {
+    outgoing: Rc<RefCell<VecDeque<u8>>>,
+    incoming: Rc<RefCell<VecDeque<u8>>>,
+    host: bool,
+}
+
+impl Loopback {
+
+    pub fn pair() -> (Self, Self) {
+
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+
+        (
+            Loopback { outgoing: a.clone(), incoming: b.clone(), host: true },
+            Loopback { outgoing: b, incoming: a, host: false },
+        )
+
+    }
+
+}
+
+impl Transport for Loopback {
+
+    fn update(&mut self) {}
+
+    fn send(&mut self, message: Message) {
+        message.encode(&mut self.outgoing.borrow_mut());
+    }
+
+    fn receive(&mut self) -> Option<Message> {
+        Message::decode(&mut self.incoming.borrow_mut())
+    }
+
+    fn is_host(&self) -> bool {
+        self.host
+    }
+
+}
+
+
+const RCNT: *mut u16 = 0x0400_0134 as *mut u16;
+const SIOCNT: *mut u16 = 0x0400_0128 as *mut u16;
+const SIOMLT_SEND: *mut u16 = 0x0400_012A as *mut u16;
+const SIOMULTI: *const u16 = 0x0400_0120 as *const u16;
+
+const MULTIPLAYER_115200: u16 = 0x2003;
+const SIOCNT_CHILD: u16 = 1 << 2;
+const SIOCNT_BUSY: u16 = 1 << 7;
+
+//a word on the cable: bit 15 is the sequence bit of the byte being sent, bit 14 the sequence bit expected next,
+//bit 8 marks a byte as present. stop and wait with one bit either way copes with missed and repeated transfers
+const SEQ: u16 = 1 << 15;
+const ACK: u16 = 1 << 14;
+const VALID: u16 = 1 << 8;
+const DISCONNECTED: u16 = 0xFFFF;
+
+//the link port in multiplayer mode, polled once a frame rather than using the serial interrupt
+pub struct SerialLink {
+    outgoing: VecDeque<u8>,
+    incoming: VecDeque<u8>,
+    seq: bool, //sequence bit of the front outgoing byte
+    expect: bool, //sequence bit of the next byte we want
+    host: bool,
+    settled: bool, //the seed has come across, until then the role can change as cables go in and out
+}
+
+impl SerialLink {
+
+    pub fn new() -> Self {
+
+        unsafe {
+            write_volatile(RCNT, 0);
+            write_volatile(SIOCNT, MULTIPLAYER_115200);
+        }
+
+        let mut link = SerialLink { outgoing: VecDeque::new(), incoming: VecDeque::new(), seq: false, expect: false, host: Self::wired_as_host(), settled: false };
+        link.load();
+        link
+
+    }
+
+    //a console on its own reads as the host, so this is only worth trusting once the other one is plugged in
+    fn wired_as_host() -> bool {
+        let control = unsafe { read_volatile(SIOCNT) };
+        control & SIOCNT_CHILD == 0
+    }
+
+    //the word the other console sees on the next transfer
+    fn load(&mut self) {
+
+        let mut word = if self.expect { ACK } else { 0 };
+
+        if let Some(&byte) = self.outgoing.front() {
+            word |= VALID | byte as u16;
+            if self.seq {
+                word |= SEQ;
+            }
+        }
+
+        unsafe { write_volatile(SIOMLT_SEND, word) };
+
+    }
+
+}
+
+impl Default for SerialLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for SerialLink {
+
+    fn update(&mut self) {
+
+        if unsafe { read_volatile(SIOCNT) } & SIOCNT_BUSY != 0 {
+            return;
+        }
+
+        //anything queued was meant for the other role, the handshake starts over from nothing
+        if !self.settled && Self::wired_as_host() != self.host {
+            self.host = !self.host;
+            self.outgoing.clear();
+            self.incoming.clear();
+            self.seq = false;
+            self.expect = false;
+        }
+
+        //the host is always player 0, so the other console is in the other slot
+        let other = unsafe { read_volatile(SIOMULTI.add(if self.host { 1 } else { 0 })) };
+
+        if other != DISCONNECTED {
+
+            if other & VALID != 0 && (other & SEQ != 0) == self.expect {
+                self.incoming.push_back(other as u8);
+                self.expect = !self.expect;
+            }
+
+            //they're waiting on the byte after ours, so ours got through
+            if !self.outgoing.is_empty() && (other & ACK != 0) != self.seq {
+                self.outgoing.pop_front();
+                self.seq = !self.seq;
+            }
+
+        }
+
+        self.load();
+
+        if self.host {
+            unsafe { write_volatile(SIOCNT, MULTIPLAYER_115200 | SIOCNT_BUSY) };
+        }
+
+    }
+
+    fn send(&mut self, message: Message) {
+        message.encode(&mut self.outgoing);
+    }
+
+    fn receive(&mut self) -> Option<Message> {
+
+        let message = Message::decode(&mut self.incoming);
+
+        if let Some(Message::Seed(_)) = message {
+            self.settled = true;
+        }
+
+        message
+
+    }
+
+    fn is_host(&self) -> bool {
+        self.host
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::logic::Cell;
+    use crate::settings::Settings;
+    use crate::Link;
+    use agb::rng::RandomNumberGenerator;
+    use alloc::vec::Vec;
+
+    const MESSAGES: [Message; 4] = [
+        Message::Seed(0xDEAD_BEEF),
+        Message::Move { dir: Direction::Right, garbage: 3 },
+        Message::Attack(2),
+        Message::GameOver { score: 123_456 },
+    ];
+
+    fn cells(link: &Link<Loopback>, side: usize) -> Vec<Cell> {
+        link.games[side].board.iter().map(|t| t.cell).collect()
+    }
+
+    #[test_case]
+    fn messages_survive_the_wire(_: &mut agb::Gba) {
+
+        let mut wire = VecDeque::new();
+
+        for message in MESSAGES {
+            message.encode(&mut wire);
+        }
+
+        for message in MESSAGES {
+            assert_eq!(Message::decode(&mut wire), Some(message));
+        }
+
+        assert!(wire.is_empty());
+
+    }
+
+    #[test_case]
+    fn partial_message_waits_for_the_rest(_: &mut agb::Gba) {
+
+        let mut bytes = VecDeque::new();
+        Message::Seed(7).encode(&mut bytes);
+
+        let mut wire: VecDeque<u8> = bytes.drain(..3).collect();
+        assert_eq!(Message::decode(&mut wire), None);
+        assert_eq!(wire.len(), 3);
+
+        wire.extend(bytes);
+        assert_eq!(Message::decode(&mut wire), Some(Message::Seed(7)));
+
+    }
+
+    #[test_case]
+    fn garbled_bytes_are_skipped(_: &mut agb::Gba) {
+
+        let mut wire: VecDeque<u8> = [0, 0xFF].into_iter().collect();
+        Message::Attack(4).encode(&mut wire);
+
+        assert_eq!(Message::decode(&mut wire), None);
+        assert_eq!(Message::decode(&mut wire), None);
+        assert_eq!(Message::decode(&mut wire), Some(Message::Attack(4)));
+
+    }
+
+    #[test_case]
+    fn loopback_battle_stays_in_step(_: &mut agb::Gba) {
+
+        let (mut host, mut other) = Loopback::pair();
+        let mut rng = RandomNumberGenerator::new_with_seed([1, 2, 3, 4]);
+        let (mut host_sent, mut other_sent) = (None, None);
+
+        //the host waits until its seed comes back
+        assert_eq!(Link::handshake(&mut host, &mut host_sent, &mut rng), None);
+        let seed = Link::handshake(&mut other, &mut other_sent, &mut rng).expect("seed sent");
+        assert_eq!(Link::handshake(&mut host, &mut host_sent, &mut rng), Some(seed));
+
+        let settings = Settings::default();
+        let mut host = Link::new(host, seed, settings);
+        let mut other = Link::new(other, seed, settings);
+
+        assert_eq!(cells(&host, 0), cells(&other, 1));
+        assert_eq!(cells(&host, 1), cells(&other, 0));
+
+        //the two starting tiles can always go one way or another
+        let moved = [Direction::Left, Direction::Right, Direction::Up, Direction::Down].into_iter().any(|dir| host.play(dir));
+        assert!(moved);
+        assert_eq!(other.listen(), None);
+        assert_eq!(cells(&host, 0), cells(&other, 1));
+
+        //an attack drops on the other board after its next move, and the host sees it land
+        host.transport.send(Message::Attack(2));
+        assert_eq!(other.listen(), None);
+        assert_eq!(other.garbage, 2);
+
+        let moved = [Direction::Left, Direction::Right, Direction::Up, Direction::Down].into_iter().any(|dir| other.play(dir));
+        assert!(moved);
+        assert_eq!(other.garbage, 0);
+        assert_eq!(cells(&other, 0).iter().filter(|&&c| c == Cell::Obstacle).count(), 2);
+
+        assert_eq!(host.listen(), None);
+        assert_eq!(cells(&host, 1), cells(&other, 0));
+
+    }
+
+}
@@ -1,11 +1,12 @@
 use agb::display::object::SpriteVram;
-use agb::display::tiled::RegularBackground;
-use agb::display::Graphics;
 use agb::println;
-use agb::{fixnum::Vector2D, rng::RandomNumberGenerator};
+use agb::{fixnum::{num, Vector2D}, rng::RandomNumberGenerator};
 extern crate alloc;
 use alloc::vec;
 use alloc::vec::Vec;
+use crate::graphics::Anim;
+use crate::tween::{Ease, Progress};
+use crate::settings::{Settings, Spawns};
 
 
 
@@ -15,13 +16,273 @@ pub enum Direction {
     Down,
     Left,
     Right,
+    In, //towards the next layer back, only moves anything on boards with depth
+    Out,
+}
+
+pub const DIRECTIONS: [Direction; 6] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right, Direction::In, Direction::Out];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+pub enum Cell {
+    Empty,
+    Number(u16),
+    Obstacle, //never moves or merges, stops anything sliding into it
+    Joker, //merges with any number it meets, upgrading that number
+    Bomb, //merges with anything that slides, then explodes
+    Hole, //a dead cell in an irregular board, a wall that is never drawn and never cleared
+}
+
+impl Cell {
+
+    //numeric value of the cell, 0 for anything that isn't a number tile
+    pub fn value(&self) -> u16 {
+        match self {
+            Cell::Number(value) => *value,
+            _ => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Cell::Empty
+    }
+
+    //whether the cell moves when the board is shifted
+    fn slides(&self) -> bool {
+        matches!(self, Cell::Number(_) | Cell::Joker | Cell::Bomb)
+    }
+
+}
+
+impl From<u16> for Cell {
+    fn from(value: u16) -> Self {
+        if value == 0 { Cell::Empty } else { Cell::Number(value) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Obstacles {
+    None,
+    Fixed(&'static [usize]), //board indices that start out walled off
+    Survival(u32), //a wall appears every n moves
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jokers {
+    None,
+    Chance(u8), //percent chance for each spawned tile to be a joker
+    Reward, //a joker spawns each time a new highest tile of REWARD_TILE or more is made
+}
+
+pub const REWARD_TILE: u16 = 64;
+
+//each merge making a tile this big or bigger sends one garbage tile in link battles
+pub const GARBAGE_TILE: u16 = 32;
+
+//everything a move changes, kept so it can be undone
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    cells: Vec<Cell>,
+    score: u32,
+    moves: u32,
+    best_tile: u16,
+    next: Cell,
+}
+
+//how much trouble a board is in and how far it's got, worked out after every move for the music
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Danger {
+    pub empty: usize, //free cells
+    pub moves: usize, //directions that would move something
+    pub highest: u16,
+    pub area: usize, //cells on the board, not counting holes
+    pub target: u16,
+}
+
+impl Danger {
+
+    //0 while half the board or more is free, rising to 1 as the rest fills. with only two directions
+    //left it's at least half way, and with one left it's as bad as it gets
+    pub fn pressure(&self) -> Progress {
+
+        let half = (self.area / 2).max(1);
+        let filling = Progress::new((half - self.empty.min(half)) as i32) / half as i32;
+
+        match self.moves {
+            1 => num!(1),
+            2 => filling.max(num!(0.5)),
+            _ => filling,
+        }
+
+    }
+
+    //how far the highest tile is towards the target, counted in doublings
+    pub fn progress(&self) -> Progress {
+        Progress::new(self.highest.max(1).ilog2() as i32) / self.target.max(2).ilog2() as i32
+    }
+
+}
+
+//area cleared when a bomb goes off
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bombs {
+    None,
+    Row,
+    Column,
+    Square, //the 3x3 neighbourhood around the bomb
+}
+
+impl Bombs {
+
+    fn area(&self, center: usize, shape: Shape) -> Vec<usize> {
+
+        let (x, y, z) = shape.coords(center);
+
+        //blasts stay within the bomb's own layer
+        (0..shape.area()).filter(|&i| {
+            let (ix, iy, iz) = shape.coords(i);
+            iz == z && match self {
+                Bombs::None => false,
+                Bombs::Row => iy == y,
+                Bombs::Column => ix == x,
+                Bombs::Square => ix.abs_diff(x) <= 1 && iy.abs_diff(y) <= 1,
+            }
+        }).collect()
+
+    }
+
+}
+
+//percent chance for each spawned tile to be a bomb, when bombs are enabled
+pub const BOMB_CHANCE: u32 = 4;
+
+//the playable area, a grid with some of its cells missing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shape {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize, //stacked layers, tiles move between them with In and Out
+    pub holes: &'static [usize], //dead cells, tiles treat them as walls and nothing spawns there
+}
+
+impl Shape {
+
+    //number of cells in the grid, holes included
+    pub fn area(&self) -> usize {
+        self.width * self.height * self.depth
+    }
+
+    //board indices run along each row, then down the rows of a layer, then through the layers
+    pub fn coords(&self, index: usize) -> (usize, usize, usize) {
+        let layer = self.width * self.height;
+        (index % self.width, (index % layer) / self.width, index / layer)
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.height + y) * self.width + x
+    }
+
+}
+
+pub const SQUARE: Shape = Shape { width: 4, height: 4, depth: 1, holes: &[] };
+pub const FIVE: Shape = Shape { width: 5, height: 5, depth: 1, holes: &[] };
+pub const PLUS: Shape = Shape { width: 5, height: 5, depth: 1, holes: &[0, 1, 3, 4, 5, 6, 8, 9, 15, 16, 18, 19, 20, 21, 23, 24] }; //a 2x2 block out of each corner
+pub const BEND: Shape = Shape { width: 5, height: 5, depth: 1, holes: &[2, 3, 4, 7, 8, 9, 12, 13, 14] }; //an L, two cells thick
+pub const RING: Shape = Shape { width: 5, height: 5, depth: 1, holes: &[6, 7, 8, 11, 12, 13, 16, 17, 18] };
+pub const LAYERS: Shape = Shape { width: 4, height: 4, depth: 2, holes: &[] };
+
+//optional extras layered on top of a ruleset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modifiers {
+    pub obstacles: Obstacles,
+    pub jokers: Jokers,
+    pub bombs: Bombs,
+    pub wrap: bool, //tiles leaving one edge come back in on the opposite one
+    pub shape: Shape,
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Self { obstacles: Obstacles::None, jokers: Jokers::None, bombs: Bombs::None, wrap: false, shape: SQUARE }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ruleset {
+    Classic,
+    Threes, //one cell slides, 1 + 2 = 3, new tiles enter from the trailing edge
+}
+
+impl Ruleset {
+
+    //the tile a game is aiming for
+    pub fn target(&self) -> u16 {
+        match self {
+            Ruleset::Classic => 2048,
+            Ruleset::Threes => 768,
+        }
+    }
+
+    //furthest a tile can slide in a single move
+    fn max_distance(&self) -> usize {
+        match self {
+            Ruleset::Classic => usize::MAX, //as far as the board allows
+            Ruleset::Threes => 1,
+        }
+    }
+
+    //cell produced by sliding `moving` into `resting`, None if they can't merge
+    fn merge(&self, moving: Cell, resting: Cell) -> Option<Cell> {
+
+        match (moving, resting) {
+            //a bomb merging with anything sets it off, the explosion is handled once the move is done
+            (Cell::Bomb, Cell::Number(_) | Cell::Joker | Cell::Bomb) | (Cell::Number(_) | Cell::Joker, Cell::Bomb) => Some(Cell::Bomb),
+            //two jokers have no value to upgrade, so they just combine into one
+            (Cell::Joker, Cell::Joker) => Some(Cell::Joker),
+            //the number always wins against a joker, and is upgraded as if merged with its twin
+            (Cell::Joker, Cell::Number(value)) | (Cell::Number(value), Cell::Joker) => Some(Cell::Number(self.upgrade(value))),
+            (Cell::Number(moving), Cell::Number(resting)) => {
+                match self {
+                    Ruleset::Classic => {
+                        if moving == resting { Some(Cell::Number(self.upgrade(moving))) } else { None }
+                    },
+                    Ruleset::Threes => {
+                        match (moving, resting) {
+                            (1, 2) | (2, 1) => Some(Cell::Number(self.upgrade(moving))),
+                            (a, b) if a >= 3 && a == b => Some(Cell::Number(self.upgrade(a))),
+                            _ => None,
+                        }
+                    },
+                }
+            },
+            _ => None,
+        }
+
+    }
+
+    //the value a tile becomes when it merges
+    fn upgrade(&self, value: u16) -> u16 {
+        match self {
+            Ruleset::Threes if value < 3 => 3,
+            _ => value * 2,
+        }
+    }
+
+    pub fn random_value(&self, rng: &mut RandomNumberGenerator, spawns: Spawns) -> u16 {
+        match self {
+            Ruleset::Classic => {
+                if scale_rng(rng.next_i32(), 0_u32, spawns.four_in() - 1) == 0 {4} else {2}
+            },
+            Ruleset::Threes => scale_rng(rng.next_i32(), 1_u32, 3_u32) as u16,
+        }
+    }
+
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
 struct Move {
     start: usize,
     end: usize,
-    upgrade: bool,
+    upgrade: Option<Cell>, //Some(merged cell) when the move merges two tiles
 }
 
 impl Ord for Move {
@@ -30,7 +291,7 @@ impl Ord for Move {
     }
 }
 
-use crate::{Game, Tile};
+use crate::{Appearing, Event, Game};
 
 pub fn scale_rng(num: i32, min: impl Into<u32>, max: impl Into<u32>) -> u32 {
 
@@ -53,35 +314,270 @@ impl Game {
 
         for (i, tile) in self.board.iter().enumerate() {
 
-            if tile.value == 0 {
+            if tile.cell.is_empty() {
+
+                blanks.push(i);
+
+            }
+
+        }
+
+        self.spawn_in(&blanks, rng);
+
+    }
+
+    //spawns on the edge opposite the swipe, so new tiles appear to slide in behind the moved ones
+    fn spawn_tile_edge(&mut self, dir: Direction, rng: &mut RandomNumberGenerator) {
+
+        let mut blanks = vec![];
+
+        let shape = self.modifiers.shape;
+
+        for (i, tile) in self.board.iter().enumerate() {
+
+            let (x, y, z) = shape.coords(i);
+
+            let on_edge = match dir {
+                Direction::Up => y == shape.height - 1,
+                Direction::Down => y == 0,
+                Direction::Left => x == shape.width - 1,
+                Direction::Right => x == 0,
+                Direction::In => z == 0,
+                Direction::Out => z == shape.depth - 1,
+            };
 
-                blanks.push((i, tile.clone()));
+            if on_edge && tile.cell.is_empty() {
+
+                blanks.push(i);
 
             }
 
         }
 
+        if blanks.is_empty() {
+            self.spawn_tile(rng);
+        } else {
+            self.spawn_in(&blanks, rng);
+        }
+
+    }
+
+    fn spawn_in(&mut self, blanks: &[usize], rng: &mut RandomNumberGenerator) {
+
+        if blanks.is_empty() {
+            return;
+        }
+
         let rand_index = scale_rng(rng.next_i32(), 0_u32, (blanks.len() - 1) as u32) as usize;
-        let rand_value = scale_rng(rng.next_i32(), 0_u32, 3_u32);
 
-        let rand_blank = &mut blanks[rand_index];
+        let rand_blank = blanks[rand_index];
 
-        self.board[rand_blank.0].update_obj = true;
-        self.board[rand_blank.0].appearing = true;
+        self.board[rand_blank].update_obj = true;
+        self.board[rand_blank].appearing = Some(Appearing::Spawned);
 
-        let rand_tile_value = if rand_value == 0 {4} else {2};
+        self.board[rand_blank].cell = self.next;
 
-        self.board[rand_blank.0].value = rand_tile_value;
-        self.score += rand_tile_value as u32;
+        self.roll_next(rng);
+        self.start_arrivals();
 
     }
 
-    pub fn shift(&mut self, dir: Direction, rng: &mut RandomNumberGenerator, gfx: &mut Graphics, bg: &RegularBackground) {
+    //picks the cell the next spawn will use
+    pub fn roll_next(&mut self, rng: &mut RandomNumberGenerator) {
+
+        if self.modifiers.bombs != Bombs::None && scale_rng(rng.next_i32(), 1_u32, 100_u32) <= BOMB_CHANCE {
+            self.next = Cell::Bomb;
+            return;
+        }
+
+        if let Jokers::Chance(percent) = self.modifiers.jokers {
+            if scale_rng(rng.next_i32(), 1_u32, 100_u32) <= percent as u32 {
+                self.next = Cell::Joker;
+                return;
+            }
+        }
+
+        self.next = Cell::Number(self.rules.random_value(rng, self.settings.spawns));
+
+    }
+
+    //walls off a random empty cell, used by survival games
+    fn spawn_obstacle(&mut self, rng: &mut RandomNumberGenerator) {
+
+        let blanks: Vec<usize> = (0..self.board.len()).filter(|i| self.board[*i].cell.is_empty()).collect();
+
+        if blanks.is_empty() {
+            return;
+        }
+
+        let rand_blank = blanks[scale_rng(rng.next_i32(), 0_u32, (blanks.len() - 1) as u32) as usize];
+
+        self.board[rand_blank].cell = Cell::Obstacle;
+        self.board[rand_blank].update_obj = true;
+        self.board[rand_blank].appearing = Some(Appearing::Spawned);
+        self.start_arrivals();
+
+    }
+
+    //blockers sent over by the opponent in link battles
+    pub fn drop_garbage(&mut self, count: u8, rng: &mut RandomNumberGenerator) {
+
+        for _ in 0..count {
+            self.spawn_obstacle(rng);
+        }
+
+        self.assess();
+
+    }
+
+    pub fn place_obstacles(&mut self) {
+
+        if let Obstacles::Fixed(indices) = self.modifiers.obstacles {
+            //skip anything that falls in a hole or off the board
+            for &i in indices {
+                if i < self.board.len() && self.board[i].cell.is_empty() {
+                    self.board[i].cell = Cell::Obstacle;
+                    self.board[i].update_obj = true;
+                }
+            }
+        }
+
+    }
+
+    //snapshot of the board contents without any of the graphics state
+    fn cells(&self) -> Vec<Cell> {
+        self.board.iter().map(|t| t.cell).collect()
+    }
+
+    //returns true if anything moved. the board changes straight away, the slide plays out over the next frames
+    pub fn shift(&mut self, dir: Direction, rng: &mut RandomNumberGenerator) -> bool {
+
+        //a move made while the last one is still sliding lands that one first
+        self.finish_animations();
+
+        let before = self.snapshot();
 
         if self.shift_tiles(dir) {
-            self.animate_move_tiles(gfx, bg);
+            self.remember(before);
+            self.hint = None;
+            self.events.push(Event::Slide);
+            self.tweens.start(Anim::Slide, self.settings.slide_animation(self.longest_slide()), Ease::Linear);
+            self.detonate();
+
             //if spawn flag is false, do not spawn new tiles
-            if self.spawn { self.spawn_tile(rng) }
+            if self.spawn {
+                match self.rules {
+                    Ruleset::Classic => self.spawn_tile(rng),
+                    Ruleset::Threes => self.spawn_tile_edge(dir, rng),
+                }
+            }
+
+            self.moves += 1;
+
+            if let Obstacles::Survival(every) = self.modifiers.obstacles {
+                if self.moves.is_multiple_of(every) {
+                    self.spawn_obstacle(rng);
+                }
+            }
+
+            self.assess();
+
+            true
+        } else {
+            self.events.push(Event::Blocked);
+            false
+        }
+
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot { cells: self.cells(), score: self.score, moves: self.moves, best_tile: self.best_tile, next: self.next }
+    }
+
+    //keeps as many boards as the undo depth setting allows, oldest dropped first
+    fn remember(&mut self, snapshot: Snapshot) {
+
+        self.history.push(snapshot);
+        self.trim_history();
+
+    }
+
+    fn trim_history(&mut self) {
+
+        let depth = self.settings.undo_depth as usize;
+
+        if self.history.len() > depth {
+            self.history.drain(..self.history.len() - depth);
+        }
+
+    }
+
+    //picks up settings changed from the pause menu part way through a game
+    pub fn apply(&mut self, settings: Settings) {
+
+        //patterns are picked when a tile's sprite is, so every tile needs it picking again
+        if settings.patterns != self.settings.patterns {
+            for tile in self.board.iter_mut() {
+                tile.update_obj = true;
+            }
+        }
+
+        self.settings = settings;
+        self.trim_history();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    //puts the board back to how it was before the last move, returns false if there's nothing to undo
+    pub fn undo(&mut self) -> bool {
+
+        let Some(snapshot) = self.history.pop() else {
+            return false;
+        };
+
+        for (tile, cell) in self.board.iter_mut().zip(snapshot.cells) {
+            tile.cell = cell;
+            tile.update_obj = true;
+            tile.animate = None;
+            tile.appearing = None;
+            tile.exploding = false;
+        }
+
+        self.score = snapshot.score;
+        self.moves = snapshot.moves;
+        self.best_tile = snapshot.best_tile;
+        self.next = snapshot.next;
+        self.hint = None;
+
+        //the points being shown were for the move that's gone
+        self.gains.clear();
+        self.popups.clear();
+
+        self.assess();
+
+        true
+
+    }
+
+    //clears the area around every bomb set off by the last move
+    fn detonate(&mut self) {
+
+        let blasts: Vec<usize> = self.blasts.drain(..).collect();
+
+        for center in blasts {
+            for i in self.modifiers.bombs.area(center, self.modifiers.shape) {
+
+                let tile = &mut self.board[i];
+
+                if !tile.cell.is_empty() && tile.cell != Cell::Hole {
+                    tile.cell = Cell::Empty;
+                    tile.exploding = true;
+                    tile.update_obj = true;
+                }
+
+            }
         }
 
     }
@@ -89,44 +585,120 @@ impl Game {
     pub fn get_score(&self) -> u32 {
         let mut score = 0;
         for tile in self.board.iter() {
-            score += tile.value as u32;
+            score += tile.cell.value() as u32;
         }
         score
     }
 
+    //sizes up the board once it's changed
+    pub fn assess(&mut self) {
+
+        let cells = self.cells();
+        let (shape, wrap) = (self.modifiers.shape, self.modifiers.wrap);
+
+        self.danger = Danger {
+            empty: cells.iter().filter(|c| c.is_empty()).count(),
+            moves: DIRECTIONS.iter().filter(|&&dir| !Move::get(&cells, dir, self.rules, shape, wrap).is_empty()).count(),
+            highest: cells.iter().map(|c| c.value()).max().unwrap_or(0),
+            area: cells.iter().filter(|&&c| c != Cell::Hole).count(),
+            target: self.rules.target(),
+        };
+
+    }
+
     pub fn check_stuck(&self) -> bool {
 
-        if self.board.len() == 16 {
+        let cells = self.cells();
 
-            if Move::get(&self.board, Direction::Up).is_empty() && Move::get(&self.board, Direction::Down).is_empty() && Move::get(&self.board, Direction::Left).is_empty() && Move::get(&self.board, Direction::Right).is_empty() {
-                return true
-            }
-        }
+        let (shape, wrap) = (self.modifiers.shape, self.modifiers.wrap);
 
-        false
+        DIRECTIONS.iter().all(|&dir| Move::get(&cells, dir, self.rules, shape, wrap).is_empty())
+
+    }
+
+    //what a hint suggests: the move with the most merges, which frees the most cells, then the one making the biggest
+    //tiles. None when nothing can move
+    pub fn best_move(&self) -> Option<Direction> {
+
+        let cells = self.cells();
+
+        let (shape, wrap) = (self.modifiers.shape, self.modifiers.wrap);
+
+        DIRECTIONS.iter().filter_map(|&dir| {
+            let moves = Move::get(&cells, dir, self.rules, shape, wrap);
+            let merges: Vec<u32> = moves.iter().filter_map(|m| m.upgrade).map(|c| c.value() as u32).collect();
+            (!moves.is_empty()).then(|| (merges.len(), merges.iter().sum::<u32>(), dir))
+        }).max_by_key(|&(count, total, _)| (count, total)).map(|(_, _, dir)| dir)
+
+    }
+
+    //the tiles the current hint would move, and where on screen each would end up
+    pub fn hinted(&self) -> Vec<(usize, Vector2D<i32>)> {
+
+        let Some(dir) = self.hint else {
+            return vec![];
+        };
+
+        let shape = self.modifiers.shape;
+
+        Move::get(&self.cells(), dir, self.rules, shape, self.modifiers.wrap).iter().map(|m| (m.start, m.travel(dir, shape))).collect()
 
     }
 
     fn shift_tiles(&mut self, dir: Direction) -> bool {
 
-        let moves = Move::get(&self.board, dir);
+        let moves = Move::get(&self.cells(), dir, self.rules, self.modifiers.shape, self.modifiers.wrap);
+
+        self.attack = 0;
 
         if !moves.is_empty() {
 
             for m in moves {
 
-                if m.upgrade {
-                    self.board[m.end].value *= 2;
+                if let Some(cell) = m.upgrade {
+                    self.board[m.end].cell = cell;
+
+                    if cell == Cell::Bomb {
+                        self.blasts.push(m.end);
+                    }
+
+                    //the score only grows from merges, by what each one makes
+                    if cell.value() > 0 {
+                        self.score += cell.value() as u32;
+                        self.gains.push((m.end, cell.value()));
+                    }
+
+                    if cell.value() >= GARBAGE_TILE {
+                        self.attack += 1;
+                    }
+
+                    if cell.value() > self.best_tile {
+                        if self.best_tile < self.rules.target() && cell.value() >= self.rules.target() {
+                            self.events.push(Event::Target);
+                        }
+
+                        self.best_tile = cell.value();
+
+                        if self.modifiers.jokers == Jokers::Reward && self.best_tile >= REWARD_TILE {
+                            self.next = Cell::Joker;
+                        }
+                    }
                 } else {
-                    self.board[m.end].value = self.board[m.start].value;
+                    self.board[m.end].cell = self.board[m.start].cell;
                 }
     
-                self.board[m.start].value = 0;
+                self.board[m.start].cell = Cell::Empty;
     
                 self.board[m.start].update_obj = true;
-                self.board[m.start].animate = Some(m.to_vec2d().1);
+                self.board[m.start].animate = Some(m.travel(dir, self.modifiers.shape));
                 self.board[m.end].update_obj = true;
-                self.board[m.end].appearing = true;
+
+                //two tiles can land in the same cell, the one that merges decides how it appears
+                if m.upgrade.is_some() {
+                    self.board[m.end].appearing = Some(Appearing::Merged);
+                } else if self.board[m.end].appearing.is_none() {
+                    self.board[m.end].appearing = Some(Appearing::Moved);
+                }
 
             }
             true
@@ -140,30 +712,52 @@ impl Game {
 
 impl Move {
 
-    fn to_vec2d(&self) -> (Vector2D<i32>, Vector2D<i32>) {
+    //layers are laid out left to right, so a tile's column counts on through the layers before it
+    fn to_vec2d(&self, shape: Shape) -> (Vector2D<i32>, Vector2D<i32>) {
 
-        let start = self.start as i32;
-        let end = self.end as i32;
+        let (start, end) = (shape.coords(self.start), shape.coords(self.end));
+        let screen = |(x, y, z): (usize, usize, usize)| Vector2D::new((z * shape.width + x) as i32, y as i32);
 
-        (Vector2D::new(start % 4, start / 4), Vector2D::new(end % 4, end / 4))
+        (screen(start), screen(end))
+
+    }
+
+    //where the tile heads on screen, past the edge of the board if it wraps around
+    fn travel(&self, dir: Direction, shape: Shape) -> Vector2D<i32> {
+
+        let (start, mut end) = self.to_vec2d(shape);
+        let (width, height) = (shape.width as i32, shape.height as i32);
+
+        match dir {
+            Direction::Up if end.y > start.y => end.y -= height,
+            Direction::Down if end.y < start.y => end.y += height,
+            Direction::Left if end.x > start.x => end.x -= width,
+            Direction::Right if end.x < start.x => end.x += width,
+            _ => {},
+        }
+
+        end
 
     }
 
     fn new(positions: (usize, usize,)) -> Self {
-        Move { start: positions.0, end: positions.1, upgrade: false }
+        Move { start: positions.0, end: positions.1, upgrade: None }
     }
 
-    fn upgrade(&mut self) {
-        self.upgrade = true;
+    fn upgrade(&mut self, cell: Cell) {
+        self.upgrade = Some(cell);
     }
 
 
-    fn validate(&mut self, board: &Vec<Tile>) -> Option<Self> {
+    //merged holds the cells that have already been merged into this move, they can't merge again
+    fn validate(&mut self, board: &[Cell], merged: &[bool], rules: Ruleset) -> Option<Self> {
 
-        if board[self.end].value == 0 {
+        if board[self.end].is_empty() {
             Some(*self)
-        } else if board[self.end].value == board[self.start].value {
-            self.upgrade();
+        } else if merged[self.end] {
+            None
+        } else if let Some(cell) = rules.merge(board[self.start], board[self.end]) {
+            self.upgrade(cell);
             Some(*self)
         } else {
             None
@@ -171,137 +765,164 @@ impl Move {
 
     }
 
-    fn move_up(board: &Vec<Tile>, index: usize) -> Option<Self> {
+    //follows the line of cells ahead of a tile, cell_at giving the cell a distance away and whether it's across the edge.
+    //the first cell that's taken ends the slide, the tile merges into it or stops short of it. on a wrapping board the
+    //line carries on through the edge, but never far enough to lap back to the tile, and a tile with nothing ahead of
+    //it in the whole line stops at the edge as it would without wrapping
+    fn slide(board: &[Cell], merged: &[bool], index: usize, rules: Ruleset, length: usize, cell_at: impl Fn(usize) -> Option<(usize, bool)>) -> Option<Self> {
 
         let mut mv = None;
+        let mut at_edge = None; //where the tile had got to before going through the edge
+        let reach = rules.max_distance().min(length - 1);
 
-        for distance in 1..=3 {
-            if index >= distance * 4 {
-                if let Some(validated) = Self::new((index, index - distance * 4)).validate(board) {
-                    mv = Some(validated);
-                } else {
-                    break;
-                }
-            } else {
-                break;
+        for distance in 1..=reach {
+
+            let Some((end, wrapped)) = cell_at(distance) else {
+                return mv;
+            };
+
+            if wrapped && at_edge.is_none() {
+                at_edge = Some(mv);
+            }
+
+            if !board[end].is_empty() {
+                return Self::new((index, end)).validate(board, merged, rules).or(mv);
             }
+
+            mv = Some(Self::new((index, end)));
+
         }
 
-        mv
+        match at_edge {
+            Some(before) if reach == length - 1 => before,
+            _ => mv,
+        }
 
     }
 
-    fn move_down(board: &Vec<Tile>, index: usize) -> Option<Self> {
+    fn move_up(board: &[Cell], merged: &[bool], index: usize, rules: Ruleset, shape: Shape, wrap: bool) -> Option<Self> {
 
-        let mut mv = None;
+        let (x, y, z) = shape.coords(index);
 
-        for distance in 1..=3 {
-            if index < 16 - distance * 4 {
-                if let Some(validated) = Self::new((index, index + distance * 4)).validate(board) {
-                    mv = Some(validated);
-                } else {
-                    break;
-                }
+        Self::slide(board, merged, index, rules, shape.height, |distance| {
+            if y >= distance {
+                Some((shape.index(x, y - distance, z), false))
+            } else if wrap {
+                Some((shape.index(x, y + shape.height - distance, z), true))
             } else {
-                break;
+                None
             }
-        }
-
-        mv
+        })
 
     }
 
-    fn move_left(board: &Vec<Tile>, index: usize) -> Option<Self> {
+    fn move_down(board: &[Cell], merged: &[bool], index: usize, rules: Ruleset, shape: Shape, wrap: bool) -> Option<Self> {
 
-        let mut mv = None;
+        let (x, y, z) = shape.coords(index);
 
-        for distance in 1..=3 {
-            if index >= distance && index - distance >= (index / 4) * 4 {
-                if let Some(validated) = Self::new((index, index - distance)).validate(board) {
-                    mv = Some(validated);
-                } else {
-                    break;
-                }
+        Self::slide(board, merged, index, rules, shape.height, |distance| {
+            if y + distance < shape.height {
+                Some((shape.index(x, y + distance, z), false))
+            } else if wrap {
+                Some((shape.index(x, y + distance - shape.height, z), true))
             } else {
-                break;
+                None
             }
-        }
+        })
+
+    }
+
+    fn move_left(board: &[Cell], merged: &[bool], index: usize, rules: Ruleset, shape: Shape, wrap: bool) -> Option<Self> {
+
+        let (x, y, z) = shape.coords(index);
 
-        mv
+        Self::slide(board, merged, index, rules, shape.width, |distance| {
+            if x >= distance {
+                Some((shape.index(x - distance, y, z), false))
+            } else if wrap {
+                Some((shape.index(x + shape.width - distance, y, z), true))
+            } else {
+                None
+            }
+        })
 
     }
 
-    fn move_right(board: &Vec<Tile>, index: usize) -> Option<Self> {
+    fn move_right(board: &[Cell], merged: &[bool], index: usize, rules: Ruleset, shape: Shape, wrap: bool) -> Option<Self> {
 
-        let mut mv = None;
+        let (x, y, z) = shape.coords(index);
 
-        for distance in 1..=3 {
-            if index + distance < ((index / 4) + 1) * 4 {
-                if let Some(validated) = Self::new((index, index + distance)).validate(board) {
-                    mv = Some(validated);
-                } else {
-                    break;
-                }
+        Self::slide(board, merged, index, rules, shape.width, |distance| {
+            if x + distance < shape.width {
+                Some((shape.index(x + distance, y, z), false))
+            } else if wrap {
+                Some((shape.index(x + distance - shape.width, y, z), true))
             } else {
-                break;
+                None
             }
-        }
+        })
+
+    }
+
+    //layers don't wrap around, a tile pushed in from the back layer has nowhere to go
+    fn move_in(board: &[Cell], merged: &[bool], index: usize, rules: Ruleset, shape: Shape) -> Option<Self> {
 
-        mv
+        let (x, y, z) = shape.coords(index);
+
+        Self::slide(board, merged, index, rules, shape.depth, |distance| {
+            (z + distance < shape.depth).then(|| (shape.index(x, y, z + distance), false))
+        })
 
     }
 
-    
+    fn move_out(board: &[Cell], merged: &[bool], index: usize, rules: Ruleset, shape: Shape) -> Option<Self> {
 
-    fn get(board: &Vec<Tile>, dir: Direction) -> Vec<Self> {
+        let (x, y, z) = shape.coords(index);
 
-        let mut moves = vec![];
+        Self::slide(board, merged, index, rules, shape.depth, |distance| {
+            (z >= distance).then(|| (shape.index(x, y, z - distance), false))
+        })
 
-        let mut board_future = board.clone();
+    }
 
-        let mut board_sorted = board.clone();
-        board_sorted.sort();
+    fn get(board: &[Cell], dir: Direction, rules: Ruleset, shape: Shape, wrap: bool) -> Vec<Self> {
 
-        match dir {
-            Direction::Up | Direction::Left => {
-                
-                for (index, _tile) in board_sorted.iter().enumerate().filter(|t| t.1.value != 0) {
-
-                    if dir == Direction::Up {
-                        if let Some(mv) = Self::move_up(&board_future, index) { 
-                            moves.push(mv);
-                            board_future = simulate(board_future, mv); 
-                        }
-                    } else {
-                        if let Some(mv) = Self::move_left(&board_future, index) {
-                            moves.push(mv);
-                            board_future = simulate(board_future, mv);
-                        }
-                    }
+        let mut moves = vec![];
 
-                }
-            },
+        let mut board_future = board.to_vec();
+        let mut merged = vec![false; board.len()];
 
-            Direction::Down | Direction::Right => {
-                board_sorted.reverse();
-                for (index, _tile) in board_sorted.iter().enumerate().filter(|t| t.1.value != 0) {
+        //tiles closest to the edge being moved towards go first, so the ones behind can slide into the space they leave
+        let indices: Vec<usize> = match dir {
+            Direction::Up | Direction::Left | Direction::Out => (0..board.len()).collect(),
+            Direction::Down | Direction::Right | Direction::In => (0..board.len()).rev().collect(),
+        };
 
-                    let index = 15 - index;
+        //cells an earlier move ended in. on a wrapping board that can be a cell still to come, and whatever's there now
+        //has already moved
+        let mut landed = vec![false; board.len()];
 
-                    if dir == Direction::Down {
-                        if let Some(mv) = Self::move_down(&board_future, index) {
-                            moves.push(mv);
-                            board_future = simulate(board_future, mv);
-                        }
-                    } else {
-                        if let Some(mv) = Self::move_right(&board_future, index) {
-                            moves.push(mv);
-                            board_future = simulate(board_future, mv);
-                        }
-                    }
+        for index in indices.into_iter().filter(|i| board[*i].slides()) {
 
-                }
+            if landed[index] {
+                continue;
+            }
+
+            let mv = match dir {
+                Direction::Up => Self::move_up(&board_future, &merged, index, rules, shape, wrap),
+                Direction::Down => Self::move_down(&board_future, &merged, index, rules, shape, wrap),
+                Direction::Left => Self::move_left(&board_future, &merged, index, rules, shape, wrap),
+                Direction::Right => Self::move_right(&board_future, &merged, index, rules, shape, wrap),
+                Direction::In => Self::move_in(&board_future, &merged, index, rules, shape),
+                Direction::Out => Self::move_out(&board_future, &merged, index, rules, shape),
+            };
+
+            if let Some(mv) = mv {
+                moves.push(mv);
+                landed[mv.end] = true;
+                simulate(&mut board_future, &mut merged, mv);
             }
+
         }
 
         moves
@@ -311,19 +932,178 @@ impl Move {
 
 }
 
-fn simulate(mut board: Vec<Tile>, mv: Move) -> Vec<Tile> {
+fn simulate(board: &mut [Cell], merged: &mut [bool], mv: Move) {
     
-    if mv.upgrade {
-        //if the move is an upgrade, tell the simulation the tile now has a value of 1, which is normally impossible and acts as a dummy tile - not upgradeable, but collidable
-        board[mv.end].value = 1;
+    if let Some(cell) = mv.upgrade {
+        board[mv.end] = cell;
+        merged[mv.end] = true;
     } else {
-        board[mv.end].value = board[mv.start].value;
+        board[mv.end] = board[mv.start];
     }
-    board[mv.start].value = 0;
-
-    board
+    board[mv.start] = Cell::Empty;
 
 }
 
+#[cfg(test)]
+mod tests {
+
+    use super::*;
 
+    const ROW: Shape = Shape { width: 4, height: 1, depth: 1, holes: &[] };
+    const COLUMN: Shape = Shape { width: 1, height: 4, depth: 1, holes: &[] };
+    const GAP: Shape = Shape { width: 4, height: 1, depth: 1, holes: &[1] }; //a row with a dead cell second from the left
+    const PAIRS: Shape = Shape { width: 2, height: 1, depth: 2, holes: &[] }; //two cells on the front layer, two behind
 
+    //plays a move the way shift_tiles does and gives back the values left on the board, and how many merges there were.
+    //the shape's holes are filled in whatever values say for them
+    fn after(values: &[u16], shape: Shape, dir: Direction, wrap: bool) -> (Vec<u16>, usize) {
+
+        let mut board: Vec<Cell> = values.iter().enumerate().map(|(i, &v)| if shape.holes.contains(&i) { Cell::Hole } else { Cell::from(v) }).collect();
+        let moves = Move::get(&board, dir, Ruleset::Classic, shape, wrap);
+
+        for m in moves.iter() {
+            board[m.end] = m.upgrade.unwrap_or(board[m.start]);
+            board[m.start] = Cell::Empty;
+        }
+
+        (board.iter().map(|c| c.value()).collect(), moves.iter().filter(|m| m.upgrade.is_some()).count())
+
+    }
+
+    #[test_case]
+    fn wrapped_tile_merges_across_the_edge(_: &mut agb::Gba) {
+        assert_eq!(after(&[2, 4, 0, 2], ROW, Direction::Left, true), (vec![4, 0, 0, 4], 1));
+        assert_eq!(after(&[2, 4, 0, 2], ROW, Direction::Right, true), (vec![4, 0, 0, 4], 1));
+    }
+
+    #[test_case]
+    fn wrapped_row_of_pairs_merges_each_pair_once(_: &mut agb::Gba) {
+        assert_eq!(after(&[2, 2, 2, 2], ROW, Direction::Left, true), (vec![4, 0, 0, 4], 2));
+        assert_eq!(after(&[2, 2, 2, 2], ROW, Direction::Right, true), (vec![4, 0, 0, 4], 2));
+    }
+
+    #[test_case]
+    fn wrapped_tile_stops_against_the_first_tile_ahead(_: &mut agb::Gba) {
+        assert_eq!(after(&[2, 2, 4, 8], ROW, Direction::Left, true), (vec![4, 4, 8, 0], 1));
+        assert_eq!(after(&[2, 0, 4, 0], ROW, Direction::Left, true), (vec![4, 0, 0, 2], 0));
+    }
+
+    #[test_case]
+    fn wrapped_lone_tile_stops_at_the_edge(_: &mut agb::Gba) {
+        assert_eq!(after(&[0, 2, 0, 0], COLUMN, Direction::Up, true), (vec![2, 0, 0, 0], 0));
+        assert_eq!(after(&[0, 2, 0, 0], COLUMN, Direction::Down, true), (vec![0, 0, 0, 2], 0));
+        assert_eq!(after(&[2, 0, 0, 0], COLUMN, Direction::Up, true), (vec![2, 0, 0, 0], 0));
+    }
+
+    #[test_case]
+    fn unwrapped_rows_slide_as_before(_: &mut agb::Gba) {
+        assert_eq!(after(&[2, 2, 2, 2], ROW, Direction::Left, false), (vec![4, 4, 0, 0], 2));
+        assert_eq!(after(&[4, 2, 2, 0], ROW, Direction::Left, false), (vec![4, 4, 0, 0], 1));
+        assert_eq!(after(&[2, 4, 0, 2], ROW, Direction::Left, false), (vec![2, 4, 2, 0], 0));
+        assert_eq!(after(&[0, 2, 2, 4], ROW, Direction::Right, false), (vec![0, 0, 4, 4], 1));
+    }
+
+    #[test_case]
+    fn holes_stop_slides(_: &mut agb::Gba) {
+        assert_eq!(after(&[0, 0, 2, 0], GAP, Direction::Left, false), (vec![0, 0, 2, 0], 0));
+        assert_eq!(after(&[2, 0, 0, 0], GAP, Direction::Right, false), (vec![2, 0, 0, 0], 0));
+        assert_eq!(after(&[0, 0, 0, 2], GAP, Direction::Left, false), (vec![0, 0, 2, 0], 0));
+    }
+
+    #[test_case]
+    fn holes_keep_tiles_apart(_: &mut agb::Gba) {
+        assert_eq!(after(&[2, 0, 2, 0], GAP, Direction::Left, false), (vec![2, 0, 2, 0], 0));
+        assert_eq!(after(&[2, 0, 2, 0], GAP, Direction::Right, false), (vec![2, 0, 0, 2], 0));
+    }
+
+    #[test_case]
+    fn holes_stop_wrapped_slides(_: &mut agb::Gba) {
+        assert_eq!(after(&[0, 0, 2, 0], GAP, Direction::Left, true), (vec![0, 0, 2, 0], 0));
+        assert_eq!(after(&[2, 0, 0, 0], GAP, Direction::Left, true), (vec![0, 0, 2, 0], 0));
+        assert_eq!(after(&[2, 0, 0, 2], GAP, Direction::Left, true), (vec![0, 0, 0, 4], 1));
+    }
+
+    #[test_case]
+    fn tiles_merge_across_layers(_: &mut agb::Gba) {
+        assert_eq!(after(&[2, 4, 2, 0], PAIRS, Direction::In, false), (vec![0, 0, 4, 4], 1));
+        assert_eq!(after(&[2, 4, 2, 0], PAIRS, Direction::Out, false), (vec![4, 4, 0, 0], 1));
+    }
+
+    #[test_case]
+    fn layers_stop_at_the_back_and_front(_: &mut agb::Gba) {
+        assert_eq!(after(&[0, 0, 2, 4], PAIRS, Direction::In, false), (vec![0, 0, 2, 4], 0));
+        assert_eq!(after(&[0, 0, 2, 4], PAIRS, Direction::In, true), (vec![0, 0, 2, 4], 0));
+        assert_eq!(after(&[2, 4, 0, 0], PAIRS, Direction::Out, true), (vec![2, 4, 0, 0], 0));
+    }
+
+    #[test_case]
+    fn layered_coords_round_trip(_: &mut agb::Gba) {
+
+        for i in 0..LAYERS.area() {
+            let (x, y, z) = LAYERS.coords(i);
+            assert_eq!(LAYERS.index(x, y, z), i);
+        }
+
+        assert_eq!(LAYERS.coords(16), (0, 0, 1));
+        assert_eq!(LAYERS.coords(31), (3, 3, 1));
+
+    }
+
+    #[test_case]
+    fn blasts_stay_in_their_layer(_: &mut agb::Gba) {
+
+        for (bombs, size) in [(Bombs::Row, 4), (Bombs::Column, 4), (Bombs::Square, 9)] {
+            for z in 0..LAYERS.depth {
+                let area = bombs.area(LAYERS.index(1, 1, z), LAYERS);
+                assert_eq!(area.len(), size);
+                assert!(area.iter().all(|&i| LAYERS.coords(i).2 == z));
+            }
+        }
+
+    }
+
+    #[test_case]
+    fn holes_never_get_spawns(_: &mut agb::Gba) {
+
+        let mut rng = RandomNumberGenerator::new_with_seed([1, 2, 3, 4]);
+        let mut game = Game::init(&mut rng, Ruleset::Classic, Modifiers { shape: PLUS, ..Modifiers::default() }, Settings::default());
+
+        //more spawns than there are cells, so the board ends up full
+        for _ in 0..PLUS.area() {
+            game.spawn_tile(&mut rng);
+        }
+
+        for (i, tile) in game.board.iter().enumerate() {
+            if PLUS.holes.contains(&i) {
+                assert_eq!(tile.cell, Cell::Hole);
+            } else {
+                assert!(!tile.cell.is_empty());
+            }
+        }
+
+    }
+
+    #[test_case]
+    fn only_merges_score(_: &mut agb::Gba) {
+
+        let mut rng = RandomNumberGenerator::new_with_seed([1, 2, 3, 4]);
+        let mut game = Game::init(&mut rng, Ruleset::Classic, Modifiers::default(), Settings::default());
+
+        for tile in game.board.iter_mut() {
+            tile.cell = Cell::Empty;
+        }
+
+        game.board[0].cell = Cell::Number(2);
+        game.board[1].cell = Cell::Number(2);
+        game.board[2].cell = Cell::Number(8);
+        game.score = 0;
+
+        game.spawn_tile(&mut rng);
+        assert_eq!(game.score, 0);
+
+        assert!(game.shift_tiles(Direction::Left));
+        assert_eq!(game.score, 4);
+
+    }
+
+}
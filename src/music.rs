@@ -0,0 +1,339 @@
+use agb::fixnum::Num;
+use agb::sound::mixer::{ChannelId, Mixer, SoundChannel};
+use agb_tracker::{include_xm, Track, TrackerInner};
+extern crate alloc;
+use alloc::borrow::Cow;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::settings::{Settings, MAX_VOLUME};
+use crate::tween::{Ease, Progress, Tweens};
+
+
+static TITLE: Track = include_xm!("sfx/title.xm");
+static GAME: Track = include_xm!("sfx/game.xm");
+static GAME_OVER: Track = include_xm!("sfx/game_over.xm");
+
+//layers over the gameplay tune, the same length and speed so they keep in time with it
+static GAME_DRIVE: Track = include_xm!("sfx/game_drive.xm"); //busier drums as the board fills up
+static GAME_LIFT: Track = include_xm!("sfx/game_lift.xm"); //an arpeggio as the tiles get bigger
+
+//frames a layer takes to fade all the way in or out
+const LAYER_FADE: i32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tune {
+    Title,
+    Game,
+    GameOver,
+}
+
+impl Tune {
+
+    //the main track first, then any layers
+    fn tracks(self) -> Vec<&'static Track> {
+        match self {
+            Tune::Title => vec![&TITLE],
+            Tune::Game => vec![&GAME, &GAME_DRIVE, &GAME_LIFT],
+            Tune::GameOver => vec![&GAME_OVER],
+        }
+    }
+
+}
+
+//what the tracker last asked of one of its channels. it's passed on to the mixer's channel after every step
+//with the volume turned down, which is how fades and the volume setting get applied to tracker music
+struct Voice {
+    sound: Option<SoundChannel>, //until it's started
+    id: Option<ChannelId>, //None if the mixer had no room for it
+    volume: Num<i16, 8>,
+    playback: Num<u32, 8>,
+    panning: Num<i16, 8>,
+    pos: Option<Num<u32, 8>>,
+    paused: bool,
+    stopped: bool,
+}
+
+impl agb_tracker::SoundChannel for Voice {
+
+    fn new(data: &Cow<'static, [u8]>) -> Self {
+
+        let sound = <SoundChannel as agb_tracker::SoundChannel>::new(data);
+
+        Self { sound: Some(sound), id: None, volume: 1.into(), playback: 1.into(), panning: 0.into(), pos: None, paused: false, stopped: false }
+
+    }
+
+    fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    fn pause(&mut self) -> &mut Self {
+        self.paused = true;
+        self
+    }
+
+    fn resume(&mut self) -> &mut Self {
+        self.paused = false;
+        self
+    }
+
+    fn should_loop(&mut self) -> &mut Self {
+        if let Some(sound) = self.sound.as_mut() {
+            sound.should_loop();
+        }
+        self
+    }
+
+    fn volume(&mut self, value: impl Into<Num<i16, 8>>) -> &mut Self {
+        self.volume = value.into();
+        self
+    }
+
+    fn restart_point(&mut self, value: impl Into<Num<u32, 8>>) -> &mut Self {
+        if let Some(sound) = self.sound.as_mut() {
+            sound.restart_point(value);
+        }
+        self
+    }
+
+    fn playback(&mut self, playback_speed: impl Into<Num<u32, 8>>) -> &mut Self {
+        self.playback = playback_speed.into();
+        self
+    }
+
+    fn panning(&mut self, panning: impl Into<Num<i16, 8>>) -> &mut Self {
+        self.panning = panning.into();
+        self
+    }
+
+    fn set_pos(&mut self, pos: impl Into<Num<u32, 8>>) -> &mut Self {
+        self.pos = Some(pos.into());
+        self
+    }
+
+}
+
+//stands in for the mixer while a track steps. channels are known to the tracker by their slot in voices,
+//and a slot is only freed once the tracker stops it so it never gets handed someone else's channel
+struct Relay<'a, 'm> {
+    mixer: &'a mut Mixer<'m>,
+    voices: &'a mut Vec<Option<Voice>>,
+    muted: bool, //nothing new is started, so a track that can't be heard takes none of the mixer's channels
+}
+
+impl agb_tracker::Mixer for Relay<'_, '_> {
+
+    type ChannelId = usize;
+    type SoundChannel = Voice;
+
+    fn channel(&mut self, slot: &usize) -> Option<&mut Voice> {
+        self.voices.get_mut(*slot).and_then(|v| v.as_mut())
+    }
+
+    fn play_sound(&mut self, mut voice: Voice) -> Option<usize> {
+
+        voice.id = voice.sound.take().filter(|_| !self.muted).and_then(|sound| self.mixer.play_sound(sound));
+
+        let slot = match self.voices.iter().position(|v| v.is_none()) {
+            Some(slot) => slot,
+            None => {
+                self.voices.push(None);
+                self.voices.len() - 1
+            },
+        };
+
+        self.voices[slot] = Some(voice);
+        Some(slot)
+
+    }
+
+}
+
+//one track and the channels it's playing on
+struct Part {
+    tracker: TrackerInner<'static, usize>,
+    voices: Vec<Option<Voice>>,
+}
+
+impl Part {
+
+    fn new(track: &'static Track) -> Self {
+        Self { tracker: TrackerInner::new(track), voices: Vec::new() }
+    }
+
+    //stopping lets go of every channel, after which the track can be dropped
+    fn step(&mut self, mixer: &mut Mixer, volume: Progress, stopping: bool) {
+
+        let mut relay = Relay { mixer, voices: &mut self.voices, muted: volume == 0.into() };
+
+        if stopping {
+            self.tracker.stop(&mut relay);
+        } else {
+            self.tracker.step(&mut relay);
+        }
+
+        for slot in self.voices.iter_mut() {
+
+            let Some(voice) = slot else {
+                continue;
+            };
+
+            let channel = voice.id.as_ref().and_then(|id| mixer.channel(id));
+
+            if voice.stopped {
+                if let Some(channel) = channel {
+                    channel.stop();
+                }
+                *slot = None;
+                continue;
+            }
+
+            //a sample that has run to its end has nothing left to change
+            let Some(channel) = channel else {
+                continue;
+            };
+
+            let scaled = (voice.volume.to_raw() as i32 * volume.to_raw()) >> 8;
+
+            channel.volume(Num::from_raw(scaled as i16)).playback(voice.playback).panning(voice.panning);
+
+            if let Some(pos) = voice.pos.take() {
+                channel.set_pos(pos);
+            }
+
+            if voice.paused {
+                channel.pause();
+            } else {
+                channel.resume();
+            }
+
+        }
+
+    }
+
+    fn is_silent(&self) -> bool {
+        self.voices.iter().all(|v| v.is_none())
+    }
+
+}
+
+//a tune's tracks, all stepped together every frame so the layers stay in time with the main track
+struct Playing {
+    tune: Tune,
+    parts: Vec<Part>, //the main track first
+    layers: Vec<Progress>, //how loud each layer is, one for each part after the first
+    wanted: Vec<Progress>, //and how loud it's heading for
+    leaving: bool, //fading out to make way for another tune
+}
+
+impl Playing {
+
+    fn new(tune: Tune) -> Self {
+
+        let parts: Vec<Part> = tune.tracks().into_iter().map(Part::new).collect();
+        let layers = vec![0.into(); parts.len() - 1];
+
+        Self { tune, parts, wanted: layers.clone(), layers, leaving: false }
+
+    }
+
+    fn step(&mut self, mixer: &mut Mixer, volume: Progress, stopping: bool) {
+
+        let step = Progress::new(1) / LAYER_FADE;
+
+        for (level, wanted) in self.layers.iter_mut().zip(&self.wanted) {
+            *level = if *level < *wanted { (*level + step).min(*wanted) } else { (*level - step).max(*wanted) };
+        }
+
+        let levels = core::iter::once(Progress::new(1)).chain(self.layers.iter().copied());
+
+        for (part, level) in self.parts.iter_mut().zip(levels) {
+            part.step(mixer, volume * level, stopping);
+        }
+
+    }
+
+    fn is_silent(&self) -> bool {
+        self.parts.iter().all(|p| p.is_silent())
+    }
+
+}
+
+//background music, one tune per scene and a cross-fade when the scene changes
+pub struct Music {
+    playing: Vec<Playing>, //the current tune last, anything before it is fading out
+    fades: Tweens<Tune>,
+}
+
+impl Music {
+
+    pub fn new() -> Self {
+        Self { playing: Vec::new(), fades: Tweens::new() }
+    }
+
+    //starts a tune fading in over the given frames while whatever was playing fades out, carrying on if it's already playing
+    pub fn cue(&mut self, tune: Tune, frames: i32) {
+
+        if self.playing.last().is_some_and(|p| p.tune == tune && !p.leaving) {
+            return;
+        }
+
+        for playing in self.playing.iter_mut().filter(|p| !p.leaving) {
+            playing.leaving = true;
+            self.fades.start(playing.tune, frames, Ease::Linear);
+        }
+
+        //a tune coming back before it's gone carries on from where it had got to
+        let playing = match self.playing.iter().position(|p| p.tune == tune) {
+            Some(i) => self.playing.remove(i),
+            None => Playing::new(tune),
+        };
+
+        self.playing.push(Playing { leaving: false, ..playing });
+
+        //no frames and the fade is over before it's looked at
+        self.fades.start(tune, frames, Ease::Linear);
+
+    }
+
+    //how loud each of the current tune's layers should be from 0 to 1, they fade towards it over LAYER_FADE frames
+    pub fn intensity(&mut self, levels: &[Progress]) {
+
+        if let Some(playing) = self.playing.last_mut().filter(|p| !p.leaving) {
+            for (wanted, &level) in playing.wanted.iter_mut().zip(levels) {
+                *wanted = level.clamp(0.into(), 1.into());
+            }
+        }
+
+    }
+
+    //once a frame before the mixer does its work
+    pub fn update(&mut self, mixer: &mut Mixer, settings: &Settings) {
+
+        self.fades.update();
+        self.fades.events(); //only whether a fade is still running matters
+
+        let volume = Progress::new(settings.music_volume as i32) / MAX_VOLUME as i32;
+
+        for playing in self.playing.iter_mut() {
+
+            let fade = self.fades.progress(playing.tune).unwrap_or(1.into());
+            let level = if playing.leaving { Progress::new(1) - fade } else { fade };
+            let stopping = playing.leaving && !self.fades.is_running(playing.tune);
+
+            playing.step(mixer, level * volume, stopping);
+
+        }
+
+        self.playing.retain(|p| !p.leaving || self.fades.is_running(p.tune) || !p.is_silent());
+
+    }
+
+}
+
+impl Default for Music {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,169 @@
+use agb::display::object::{Object, SpriteVram};
+use agb::display::{GraphicsFrame, Priority};
+use agb::fixnum::Vector2D;
+extern crate alloc;
+use alloc::vec::Vec;
+
+use crate::tile_gfx;
+use crate::tween::{Ease, Progress};
+
+
+//objects set aside for popups, a move with lots of merges drops the oldest popups rather than using more
+const POOL_SIZE: usize = 24;
+const GLYPH_WIDTH: i32 = 6; //the digits are 8 pixels wide but only 6 apart
+const LIFETIME: i32 = 40; //frames a merge popup stays up
+const FLIGHT: i32 = 32; //frames the move total takes to reach the score
+const RISE: i32 = 12; //pixels a merge popup climbs
+
+//one "+N" on screen
+#[derive(Debug, Clone, Copy)]
+struct Popup {
+    value: u32,
+    from: Vector2D<i32>, //centre of the number when it appears
+    to: Vector2D<i32>, //and when it disappears
+    age: i32,
+    lifetime: i32,
+    ease: Ease,
+}
+
+impl Popup {
+
+    //sprite frames, the plus followed by the digits
+    fn glyphs(&self) -> Vec<Option<usize>> {
+
+        let mut digits = Vec::new();
+        let mut value = self.value;
+
+        loop {
+            digits.push(Some((value % 10) as usize));
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+
+        digits.push(None);
+        digits.reverse();
+        digits
+
+    }
+
+    fn centre(&self) -> Vector2D<i32> {
+
+        let progress = self.ease.apply(Progress::new(self.age) / self.lifetime);
+        let travel: Vector2D<Progress> = (self.to - self.from).change_base();
+
+        self.from + (travel * progress).floor()
+
+    }
+
+    //fades out over the last quarter of its life by blinking
+    fn is_visible(&self) -> bool {
+        self.age < self.lifetime * 3 / 4 || self.age % 2 == 0
+    }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Popups {
+    pool: Vec<Object>,
+    live: Vec<Popup>, //oldest first
+}
+
+impl Popups {
+
+    pub fn new() -> Self {
+
+        let pool = (0..POOL_SIZE).map(|_| {
+            let mut object = Object::new(SpriteVram::from(tile_gfx::GAIN.sprite(0)));
+            object.set_priority(Priority::P0);
+            object
+        }).collect();
+
+        Self { pool, live: Vec::new() }
+
+    }
+
+    //a number floating up from a merge, still ones just appear and go
+    pub fn rise(&mut self, value: u32, at: Vector2D<i32>, still: bool) {
+
+        let to = if still { at } else { at - Vector2D::new(0, RISE) };
+
+        self.add(Popup { value, from: at, to, age: 0, lifetime: LIFETIME, ease: Ease::Out });
+
+    }
+
+    //a number flying across the screen, still ones show up at the end
+    pub fn fly(&mut self, value: u32, from: Vector2D<i32>, to: Vector2D<i32>, still: bool) {
+
+        let from = if still { to } else { from };
+
+        self.add(Popup { value, from, to, age: 0, lifetime: FLIGHT, ease: Ease::InOut });
+
+    }
+
+    fn add(&mut self, popup: Popup) {
+
+        self.live.push(popup);
+
+        while self.live.iter().map(|p| p.glyphs().len()).sum::<usize>() > POOL_SIZE {
+            self.live.remove(0);
+        }
+
+    }
+
+    pub fn update(&mut self) {
+
+        for popup in self.live.iter_mut() {
+            popup.age += 1;
+        }
+
+        self.live.retain(|p| p.age < p.lifetime);
+
+    }
+
+    pub fn clear(&mut self) {
+        self.live.clear();
+    }
+
+    //newest in front
+    pub fn show(&mut self, frame: &mut GraphicsFrame) {
+
+        let mut objects = self.pool.iter_mut();
+
+        for popup in self.live.iter().rev().filter(|p| p.is_visible()) {
+
+            let glyphs = popup.glyphs();
+            let centre = popup.centre();
+            let mut x = centre.x - GLYPH_WIDTH * glyphs.len() as i32 / 2;
+
+            for glyph in glyphs {
+
+                let Some(object) = objects.next() else {
+                    return;
+                };
+
+                let sprite = match glyph {
+                    Some(digit) => tile_gfx::FIGURES.sprite(digit),
+                    None => tile_gfx::GAIN.sprite(0),
+                };
+
+                object.set_sprite(SpriteVram::from(sprite));
+                object.set_pos(Vector2D::new(x, centre.y - 4));
+                object.show(frame);
+
+                x += GLYPH_WIDTH;
+
+            }
+
+        }
+
+    }
+
+}
+
+impl Default for Popups {
+    fn default() -> Self {
+        Self::new()
+    }
+}
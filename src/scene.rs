@@ -0,0 +1,761 @@
+use agb::display::tiled::{RegularBackground, RegularBackgroundId, RegularBackgroundSize, TileFormat};
+use agb::display::{Graphics, GraphicsFrame, Priority};
+use agb::fixnum::{num, Num, Rect, Vector2D};
+use agb::input::Button;
+use agb::rng::RandomNumberGenerator;
+use agb::save::SaveManager;
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::graphics::{darken, dim};
+use crate::hud::Hud;
+use crate::lang::Id;
+use crate::theme::Palettes;
+use crate::text::{Align, Colour, Effect, Style, Text, COLUMNS};
+use crate::link::{SerialLink, Transport};
+use crate::logic::{Danger, SQUARE};
+use crate::music::{Music, Tune};
+use crate::sfx::{Sound, Sounds};
+use crate::input::Input;
+use crate::settings::{SaveFile, Settings, Storage};
+use crate::tween::{Ease, Progress, Tweens};
+use crate::{background, Event, Game, Link, Menu, Mode, Options, Pause, PauseItem, Setup, Versus};
+
+
+//everything that outlives a single scene
+pub struct Context {
+    pub input: Input,
+    pub rng: RandomNumberGenerator,
+    pub menu: Menu,
+    pub setup: Setup,
+    pub pause: Pause,
+    pub options: Options,
+    pub settings: Settings,
+    pub storage: Storage,
+    pub bg: RegularBackground,
+    pub bg_versus: RegularBackground,
+    pub bg_plain: RegularBackground, //only the square board is drawn on bg, other shapes go on this
+    pub hud: Hud,
+    pub text: Text, //captions on full screen scenes
+    pub overlay: Text, //the pause menu and options screen, in front of whatever they're opened over
+    pub age: i32, //frames since the top scene was entered, for text effects
+    pub palettes: Palettes,
+    pub music: Music,
+    pub sounds: Sounds,
+}
+
+impl Context {
+
+    pub fn new(save: SaveManager) -> Self {
+
+        let mut bg = RegularBackground::new(Priority::P0, RegularBackgroundSize::Background32x32, TileFormat::FourBpp);
+        bg.fill_with(&background::bg);
+
+        let mut bg_versus = RegularBackground::new(Priority::P0, RegularBackgroundSize::Background32x32, TileFormat::FourBpp);
+        bg_versus.fill_with(&background::bg_versus);
+
+        let mut bg_plain = RegularBackground::new(Priority::P0, RegularBackgroundSize::Background32x32, TileFormat::FourBpp);
+        bg_plain.fill_with(&background::bg_plain);
+
+        let mut storage = Storage::new(save);
+        let saved = storage.load();
+
+        let mut menu = Menu::new();
+        menu.high_score = saved.high_score;
+
+        Self {
+            input: Input::new(&saved.settings),
+            rng: RandomNumberGenerator::new(),
+            menu,
+            setup: Setup::new(),
+            pause: Pause::new(),
+            options: Options::new(),
+            settings: saved.settings,
+            storage,
+            bg,
+            bg_versus,
+            bg_plain,
+            hud: Hud::new(),
+            text: Text::new(Priority::P0),
+            overlay: Text::new(Priority::P0),
+            age: 0,
+            palettes: Palettes::new(),
+            music: Music::new(),
+            sounds: Sounds::new(),
+        }
+
+    }
+
+    //writes the settings and high score to save memory
+    pub fn save(&mut self) {
+        self.storage.store(&SaveFile { settings: self.settings, high_score: self.menu.high_score });
+    }
+
+}
+
+//whatever is being played, one board or two
+pub enum Play {
+    Solo(Game),
+    Versus(Versus),
+    Link(Link<SerialLink>),
+}
+
+impl Play {
+
+    //a fresh game with the same rules, None for link battles since the other console would need to agree
+    fn restart(&self, rng: &mut RandomNumberGenerator, settings: Settings) -> Option<Play> {
+
+        match self {
+            Play::Solo(game) => Some(Play::Solo(Game::init(rng, game.rules, game.modifiers, settings))),
+            Play::Versus(versus) => Some(Play::Versus(Versus::init(rng, versus.contest, settings))),
+            Play::Link(_) => None,
+        }
+
+    }
+
+    //only single player boards can be taken back, anything else would be unfair on the opponent
+    fn can_undo(&self) -> bool {
+        matches!(self, Play::Solo(game) if game.can_undo())
+    }
+
+    fn undo(&mut self) {
+        if let Play::Solo(game) = self {
+            game.undo();
+        }
+    }
+
+    //link boards keep the settings they started with so both consoles stay in step
+    fn apply(&mut self, settings: Settings) {
+        match self {
+            Play::Solo(game) => game.apply(settings),
+            Play::Versus(versus) => versus.games.iter_mut().for_each(|g| g.apply(settings)),
+            Play::Link(_) => {},
+        }
+    }
+
+    //link boards too, colours don't need the other console to agree
+    fn reload(&mut self) {
+        match self {
+            Play::Solo(game) => game.reload(),
+            Play::Versus(versus) => versus.games.iter_mut().for_each(|g| g.reload()),
+            Play::Link(link) => link.games.iter_mut().for_each(|g| g.reload()),
+        }
+    }
+
+    fn animate(&mut self) {
+        match self {
+            Play::Solo(game) => game.animate(),
+            Play::Versus(versus) => versus.games.iter_mut().for_each(|g| g.animate()),
+            Play::Link(link) => link.games.iter_mut().for_each(|g| g.animate()),
+        }
+    }
+
+    //what's happened on the boards since last time, the other console's board in link battles stays quiet
+    fn events(&mut self) -> Vec<Event> {
+        match self {
+            Play::Solo(game) => game.events.drain(..).collect(),
+            Play::Versus(versus) => versus.games.iter_mut().flat_map(|g| g.events.drain(..)).collect(),
+            Play::Link(link) => {
+                link.games[1].events.clear();
+                link.games[0].events.drain(..).collect()
+            },
+        }
+    }
+
+    //the board in the most trouble sets how worked up the music gets
+    fn danger(&self) -> Danger {
+        match self {
+            Play::Solo(game) => game.danger,
+            Play::Versus(versus) => versus.games.iter().map(|g| g.danger).max_by_key(|d| d.pressure()).unwrap_or_default(),
+            Play::Link(link) => link.games[0].danger,
+        }
+    }
+
+    fn score(&self) -> u32 {
+        match self {
+            Play::Solo(game) => game.score,
+            Play::Versus(versus) => versus.games.iter().map(|g| g.score).max().unwrap_or(0),
+            Play::Link(link) => link.games[0].score,
+        }
+    }
+
+}
+
+//every game goes through here when it ends, whether it was decided or quit from the pause menu
+fn finish(ctx: &mut Context, play: &Play) {
+
+    ctx.menu.last_score = play.score();
+
+    //the best is for single player games, two player boards don't play by the same rules
+    if let Play::Solo(game) = play {
+        ctx.menu.high_score = ctx.menu.high_score.max(game.score);
+    }
+
+    ctx.save();
+
+}
+
+//where the title and game over screens print their line of text, under the board
+const CAPTION_ROW: i32 = 19;
+
+pub enum Scene {
+    Title, //also where the mode is picked, L and R cycle through them
+    Setup, //custom rules
+    Connecting { link: Option<SerialLink>, seed: Option<u32> }, //waiting for the other console, the link moves into the game once it's found
+    Playing { play: Box<Play>, ending: i32 }, //ending counts down the frames the final board stays up once the game is decided
+    Paused, //overlay on top of a game
+    Options, //overlay on top of the title or the pause menu
+    GameOver,
+}
+
+//what a scene wants to happen after its update
+pub enum Change {
+    Stay,
+    Switch(Scene, Transition), //replaces every scene on the stack
+    Push(Scene), //opens an overlay
+    Pop, //closes the top overlay
+}
+
+//effect played between two scenes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transition {
+    Cut,
+    Fade,
+    Start, //title letters fly apart
+    GameOver, //long fade to black
+}
+
+impl Scene {
+
+    //called when the scene becomes the top of the stack
+    fn enter(&mut self, ctx: &mut Context) {
+
+        ctx.age = 0;
+        ctx.text.clear();
+        ctx.overlay.clear();
+
+        match self {
+            Scene::Title => {
+                ctx.menu.game_over = false;
+                ctx.menu.set();
+            },
+            Scene::Setup => ctx.setup.set(),
+            Scene::Paused => ctx.pause.set(),
+            Scene::GameOver => {
+                ctx.menu.game_over = true;
+                ctx.menu.set();
+            },
+            _ => {},
+        }
+
+    }
+
+    //below is the scene an overlay was opened over
+    fn update(&mut self, ctx: &mut Context, below: Option<&mut Scene>) -> Change {
+
+        match self {
+
+            Scene::Title => {
+
+                ctx.menu.test(ctx.input.buttons.is_pressed(Button::SELECT));
+
+                if ctx.input.buttons.is_just_pressed(Button::R) {
+                    ctx.menu.mode(ctx.menu.mode.next());
+                } else if ctx.input.buttons.is_just_pressed(Button::L) {
+                    ctx.menu.mode(ctx.menu.mode.previous());
+                }
+
+                if ctx.input.buttons.is_just_pressed(ctx.settings.back()) {
+                    return Change::Push(Scene::Options);
+                }
+
+                if !ctx.input.buttons.is_just_pressed(ctx.settings.confirm()) {
+                    return Change::Stay;
+                }
+
+                if ctx.menu.test {
+                    let tile_values = [
+                        8, 16, 8, 16,
+                        16, 8, 16, 8,
+                        8, 16, 8, 16,
+                        16, 0, 16, 8,
+                    ];
+                    return Change::Switch(Scene::playing(Play::Solo(Game::init_with_board(tile_values, ctx.settings))), Transition::Start);
+                }
+
+                match ctx.menu.mode {
+                    Mode::Custom => Change::Switch(Scene::Setup, Transition::Fade),
+                    Mode::Link => Change::Switch(Scene::Connecting { link: Some(SerialLink::new()), seed: None }, Transition::Fade),
+                    mode => {
+                        let play = match mode.contest() {
+                            Some(contest) => Play::Versus(Versus::init(&mut ctx.rng, contest, ctx.settings)),
+                            None => Play::Solo(Game::init(&mut ctx.rng, mode.ruleset(), mode.modifiers(), ctx.settings)),
+                        };
+                        Change::Switch(Scene::playing(play), Transition::Start)
+                    },
+                }
+
+            },
+
+            Scene::Setup => {
+
+                match ctx.setup.update(&ctx.input.buttons, &ctx.settings) {
+                    Some(true) => {
+                        let game = Game::init(&mut ctx.rng, ctx.setup.ruleset, ctx.setup.modifiers, ctx.settings);
+                        Change::Switch(Scene::playing(Play::Solo(game)), Transition::Fade)
+                    },
+                    Some(false) => Change::Switch(Scene::Title, Transition::Fade),
+                    None => Change::Stay,
+                }
+
+            },
+
+            Scene::Connecting { link: slot, seed } => {
+
+                if ctx.input.buttons.is_just_pressed(ctx.settings.back()) {
+                    return Change::Switch(Scene::Title, Transition::Cut);
+                }
+
+                let Some(transport) = slot.as_mut() else {
+                    return Change::Stay;
+                };
+
+                transport.update();
+
+                match Link::handshake(transport, seed, &mut ctx.rng) {
+                    Some(seed) => {
+                        let transport = slot.take().expect("checked above");
+                        Change::Switch(Scene::playing(Play::Link(Link::new(transport, seed, ctx.settings))), Transition::Cut)
+                    },
+                    None => Change::Stay,
+                }
+
+            },
+
+            Scene::Playing { play, ending } => {
+
+                //the game is decided, keep the final boards up for a moment
+                if *ending > 0 {
+                    *ending -= 1;
+
+                    play.animate();
+                    play.events().into_iter().for_each(|e| ctx.sounds.play(e.into()));
+
+                    //and keep the link running long enough for the last messages to get across
+                    if let Play::Link(link) = play.as_mut() {
+                        link.transport.update();
+                    }
+
+                    if *ending > 0 {
+                        return Change::Stay;
+                    }
+
+                    finish(ctx, play);
+                    return Change::Switch(Scene::GameOver, Transition::GameOver);
+                }
+
+                if ctx.input.buttons.is_just_pressed(Button::START) {
+                    return Change::Push(Scene::Paused);
+                }
+
+                let finished = match play.as_mut() {
+                    Play::Solo(game) => game.update(&mut ctx.input, &mut ctx.rng),
+                    Play::Versus(versus) => versus.update(&mut ctx.input).is_some(),
+                    Play::Link(link) => link.update(&mut ctx.input).is_some(),
+                };
+
+                play.events().into_iter().for_each(|e| ctx.sounds.play(e.into()));
+
+                //drums build as the board fills, the arpeggio as the tiles grow
+                let danger = play.danger();
+                ctx.music.intensity(&[danger.pressure(), danger.progress()]);
+
+                if finished {
+                    ctx.sounds.play(Sound::GameOver);
+
+                    *ending = match play.as_ref() {
+                        Play::Solo(_) => ctx.settings.tile_animation() * 3, //long enough for the last slide and blast
+                        Play::Versus(_) => ctx.settings.start_animation() * 2,
+                        Play::Link(_) => ctx.settings.start_animation() * 4,
+                    };
+                }
+
+                Change::Stay
+
+            },
+
+            Scene::Paused => {
+
+                let Some(Scene::Playing { play, .. }) = below else {
+                    return Change::Pop;
+                };
+
+                //anything changed on the options screen takes effect once the game carries on
+                play.apply(ctx.settings);
+
+                let available = |item: PauseItem| match item {
+                    PauseItem::Restart => !matches!(play.as_ref(), Play::Link(_)),
+                    PauseItem::Undo => play.can_undo(),
+                    _ => true,
+                };
+
+                match ctx.pause.update(&ctx.input.buttons, &ctx.settings, available) {
+                    Some(PauseItem::Resume) => Change::Pop,
+                    Some(PauseItem::Restart) => {
+                        if let Some(restarted) = play.restart(&mut ctx.rng, ctx.settings) {
+                            **play = restarted;
+                        }
+                        Change::Pop
+                    },
+                    Some(PauseItem::Undo) => {
+                        play.undo();
+                        Change::Pop
+                    },
+                    Some(PauseItem::Quit) => {
+                        finish(ctx, play);
+                        Change::Switch(Scene::Title, Transition::Fade)
+                    },
+                    Some(PauseItem::Options) => Change::Push(Scene::Options),
+                    None => Change::Stay,
+                }
+
+            },
+
+            Scene::Options => {
+
+                if ctx.options.update(&ctx.input.buttons, &mut ctx.settings) {
+                    ctx.input.configure(&ctx.settings);
+                    ctx.save();
+                    Change::Pop
+                } else {
+                    Change::Stay
+                }
+
+            },
+
+            Scene::GameOver => {
+
+                if ctx.input.buttons.is_just_pressed(ctx.settings.confirm()) {
+                    Change::Switch(Scene::Title, Transition::Fade)
+                } else {
+                    Change::Stay
+                }
+
+            },
+
+        }
+
+    }
+
+    //draws the scene, covered is the overlay sitting on top of it. returns the backgrounds shown
+    fn render(&mut self, ctx: &mut Context, frame: &mut GraphicsFrame, covered: Option<&Cover>) -> Vec<RegularBackgroundId> {
+
+        match self {
+
+            Scene::Title | Scene::GameOver => {
+                let language = ctx.settings.language;
+                let caption = if matches!(self, Scene::Title) {
+                    (format!("{} {}", Id::Best.text(language), ctx.menu.high_score), Style::new(Colour::Dark, Align::Centre).with(Effect::Shine(ctx.age)))
+                } else {
+                    (format!("{} {}", Id::Score.text(language), ctx.menu.last_score), Style::new(Colour::Accent, Align::Centre).with(Effect::Typed(ctx.age)))
+                };
+                ctx.text.print(Vector2D::new(0, CAPTION_ROW), COLUMNS, &caption.0, caption.1);
+                ctx.menu.print(&mut ctx.text, language);
+
+                let layers = vec![ctx.text.show(frame), ctx.bg.show(frame)];
+                match covered {
+                    Some(cover) => dim(frame, &layers, cover.area, &cover.layers),
+                    None => ctx.menu.show(frame),
+                }
+                layers
+            },
+
+            Scene::Setup => {
+                ctx.setup.show(frame, &mut ctx.text, ctx.settings.language);
+                let (text_id, bg_id) = (ctx.text.show(frame), ctx.bg.show(frame));
+                frame.blend().object_transparency(num!(0.5), num!(0.5)).enable_background(bg_id);
+                vec![text_id, bg_id]
+            },
+
+            //over the two empty boards
+            Scene::Connecting { .. } => {
+                let waiting = Style::new(Colour::Light, Align::Centre).with(Effect::Typed(ctx.age % 60));
+                let language = ctx.settings.language;
+                ctx.text.print(Vector2D::new(0, 9), COLUMNS / 2, Id::PlayerOne.text(language), Style::new(Colour::Light, Align::Centre));
+                ctx.text.print(Vector2D::new(COLUMNS / 2, 9), COLUMNS / 2, Id::Waiting.text(language), waiting);
+                vec![ctx.text.show(frame), ctx.bg_versus.show(frame)]
+            },
+
+            Scene::Playing { play, .. } => {
+                let layers = match play.as_mut() {
+                    Play::Solo(game) => {
+                        let bg = if game.modifiers.shape == SQUARE { &ctx.bg } else { &ctx.bg_plain };
+                        let hud = ctx.hud.show(frame, game, ctx.menu.high_score.max(game.score), ctx.settings.language);
+                        game.show(frame, bg, hud)
+                    },
+                    Play::Versus(versus) => {
+                        let hud = ctx.hud.show_scores(frame, &versus.games, ctx.settings.language);
+                        crate::show_boards(&mut versus.games, frame, &ctx.bg_versus, hud)
+                    },
+                    Play::Link(link) => {
+                        let hud = ctx.hud.show_scores(frame, &link.games, ctx.settings.language);
+                        crate::show_boards(&mut link.games, frame, &ctx.bg_versus, hud)
+                    },
+                };
+                if let Some(cover) = covered {
+                    dim(frame, &layers, cover.area, &cover.layers);
+                }
+                layers
+            },
+
+            //hidden while the options screen is open over it, the two share the overlay layer
+            Scene::Paused => {
+                if covered.is_some() {
+                    return vec![];
+                }
+                ctx.pause.show(&mut ctx.overlay, ctx.settings.language);
+                vec![ctx.overlay.show(frame)]
+            },
+
+            Scene::Options => {
+                ctx.options.show(&mut ctx.overlay, &ctx.settings);
+                vec![ctx.overlay.show(frame)]
+            },
+
+        }
+
+    }
+
+    //background music for the scene, None for overlays which keep what's playing under them
+    fn tune(&self) -> Option<Tune> {
+        match self {
+            Scene::Title | Scene::Setup | Scene::Connecting { .. } => Some(Tune::Title),
+            Scene::Playing { .. } => Some(Tune::Game),
+            Scene::GameOver => Some(Tune::GameOver),
+            Scene::Paused | Scene::Options => None,
+        }
+    }
+
+    //the part of the screen an overlay draws over, None for full screen scenes
+    fn area(&self, ctx: &Context) -> Option<Rect<i32>> {
+        match self {
+            Scene::Paused => Some(ctx.pause.area()),
+            Scene::Options => Some(ctx.options.area()),
+            _ => None,
+        }
+    }
+
+    fn playing(play: Play) -> Self {
+        Scene::Playing { play: Box::new(play), ending: 0 }
+    }
+
+}
+
+//the topmost overlay, what's under it is dimmed apart from the area it draws over where its own layers show
+struct Cover {
+    area: Rect<i32>,
+    layers: Vec<RegularBackgroundId>,
+}
+
+//which half of a transition is playing
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Out, //the old scene going
+    In, //the new one arriving
+}
+
+impl Transition {
+
+    //frames taken by each phase
+    fn lengths(self, settings: &Settings) -> (i32, i32) {
+
+        let time = settings.start_animation();
+
+        match self {
+            Transition::Cut => (0, 0),
+            Transition::Fade => (time / 2, time / 2),
+            Transition::Start => (time, time / 2),
+            Transition::GameOver => (time * 4, time),
+        }
+
+    }
+
+}
+
+//a switch in progress, the next scene waits here until the old one has gone
+struct Switching {
+    transition: Transition,
+    next: Option<Scene>,
+    tweens: Tweens<Phase>,
+}
+
+//the top scene gets updated, every scene on the stack gets drawn
+pub struct Scenes {
+    stack: Vec<Scene>,
+    switching: Option<Switching>, //scenes don't get updated while this plays
+}
+
+impl Scenes {
+
+    pub fn new(mut first: Scene, ctx: &mut Context) -> Self {
+
+        if let Some(tune) = first.tune() {
+            ctx.music.cue(tune, 0);
+        }
+
+        first.enter(ctx);
+        Self { stack: vec![first], switching: None }
+
+    }
+
+    pub fn update(&mut self, ctx: &mut Context) {
+
+        ctx.input.update();
+        ctx.rng.next_i32(); //call every frame to randomize rng state
+        ctx.age += 1;
+
+        if self.switching.is_some() {
+            self.update_switching(ctx);
+            return;
+        }
+
+        let Some((top, rest)) = self.stack.split_last_mut() else {
+            return;
+        };
+
+        match top.update(ctx, rest.last_mut()) {
+            Change::Stay => {},
+            Change::Switch(scene, transition) => self.switch(scene, transition, ctx),
+            Change::Push(mut scene) => {
+                scene.enter(ctx);
+                self.stack.push(scene);
+            },
+            Change::Pop => {
+                self.stack.pop();
+                if let Some(top) = self.stack.last_mut() {
+                    top.enter(ctx);
+                }
+            },
+        }
+
+    }
+
+    fn switch(&mut self, scene: Scene, transition: Transition, ctx: &mut Context) {
+
+        //the title letters flying apart is the one big movement, reduced motion fades instead
+        let transition = if transition == Transition::Start && ctx.settings.reduced_motion { Transition::Fade } else { transition };
+
+        let (out, arriving) = transition.lengths(&ctx.settings);
+
+        //the music cross-fades across the whole transition, out and back in
+        if let Some(tune) = scene.tune() {
+            ctx.music.cue(tune, out + arriving);
+        }
+
+        if out == 0 {
+            self.replace(scene, ctx);
+            return;
+        }
+
+        let mut tweens = Tweens::new();
+        tweens.start(Phase::Out, out, Ease::InOut);
+
+        self.switching = Some(Switching { transition, next: Some(scene), tweens });
+
+    }
+
+    fn replace(&mut self, mut scene: Scene, ctx: &mut Context) {
+        scene.enter(ctx);
+        self.stack.clear();
+        self.stack.push(scene);
+    }
+
+    fn update_switching(&mut self, ctx: &mut Context) {
+
+        let Some(switching) = self.switching.as_mut() else {
+            return;
+        };
+
+        switching.tweens.update();
+
+        let mut done = false;
+
+        for phase in switching.tweens.events() {
+            match phase {
+                Phase::Out => {
+                    let (_, length) = switching.transition.lengths(&ctx.settings);
+                    switching.tweens.start(Phase::In, length, Ease::InOut);
+
+                    if let Some(scene) = switching.next.take() {
+                        self.replace(scene, ctx);
+                        break;
+                    }
+                },
+                Phase::In => done = true,
+            }
+        }
+
+        if done {
+            self.switching = None;
+        }
+
+    }
+
+    pub fn render(&mut self, ctx: &mut Context, gfx: &mut Graphics) {
+
+        let mut frame = gfx.frame();
+
+        //sprites take the theme's colours as they're loaded, so when it changes everything holding one loads it again.
+        //checked every frame so changes on the options screen show straight away
+        if ctx.palettes.update(ctx.settings.theme, ctx.settings.vision) {
+            ctx.menu.set_objs();
+            ctx.setup.set();
+            for scene in self.stack.iter_mut() {
+                if let Scene::Playing { play, .. } = scene {
+                    play.reload();
+                }
+            }
+        }
+
+        match self.switching.as_ref() {
+            Some(switching) => {
+                //only the bottom scene takes part, overlays go as soon as the switch starts
+                let darkness = match (switching.tweens.progress(Phase::Out), switching.tweens.progress(Phase::In)) {
+                    (Some(progress), _) if switching.transition == Transition::Start => {
+                        //the screen goes dark between a quarter and half way through the letters flying apart
+                        let text_id = ctx.text.show_scrolled(&mut frame, Vector2D::new(0, ctx.menu.start_rise(progress)));
+                        let bg_id = ctx.bg.show(&mut frame);
+                        ctx.menu.show_start(&mut frame, progress);
+                        Some((vec![text_id, bg_id], progress * 4 - 1))
+                    },
+                    (Some(progress), _) => Some((self.stack[0].render(ctx, &mut frame, None), progress)),
+                    (None, Some(progress)) => Some((self.stack[0].render(ctx, &mut frame, None), Progress::new(1) - progress)),
+                    (None, None) => None,
+                };
+
+                if let Some((layers, amount)) = darkness {
+                    let amount = (amount * 16).floor().clamp(0, 16) as u8;
+                    darken(&mut frame, &layers, Num::from_raw(amount));
+                }
+            },
+            None => {
+                //top down, objects shown first are drawn in front so overlays stay on top of what they cover
+                let mut covered: Option<Cover> = None;
+
+                for scene in self.stack.iter_mut().rev() {
+                    let layers = scene.render(ctx, &mut frame, covered.as_ref());
+                    if covered.is_none() {
+                        covered = scene.area(ctx).map(|area| Cover { area, layers });
+                    }
+                }
+            },
+        }
+
+        frame.commit();
+
+    }
+
+}
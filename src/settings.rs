@@ -0,0 +1,329 @@
+use agb::input::Button;
+use agb::save::{Error, SaveManager};
+
+use crate::lang::Language;
+use crate::tween::Ease;
+
+
+//how fast tiles slide and screens change
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    Slow,
+    Normal,
+    Fast,
+}
+
+impl Speed {
+
+    //frames for one tile slide at this speed
+    fn frames(self) -> i32 {
+        match self {
+            Speed::Slow => 12,
+            Speed::Normal => 8,
+            Speed::Fast => 4,
+        }
+    }
+
+}
+
+//how often a new tile is a 4 rather than a 2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spawns {
+    Classic, //one in four
+    Generous, //one in two
+    Stingy, //one in ten
+}
+
+impl Spawns {
+
+    pub fn four_in(self) -> u32 {
+        match self {
+            Spawns::Classic => 4,
+            Spawns::Generous => 2,
+            Spawns::Stingy => 10,
+        }
+    }
+
+}
+
+//colours the whole game is drawn in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Theme {
+    Classic,
+    Dark,
+    Contrast,
+    Pastel,
+    Green, //the original game boy's greens
+}
+
+//tile colours reworked for colour blindness, on top of the theme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Vision {
+    Normal,
+    Deuteranopia, //green weak
+    Protanopia, //red weak
+    Tritanopia, //blue weak
+}
+
+//how tiles speed up and slow down as they slide
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    Linear,
+    Ease, //fast start, gentle landing
+    Back, //overshoots and settles into place
+}
+
+impl Curve {
+
+    pub fn ease(self) -> Ease {
+        match self {
+            Curve::Linear => Ease::Linear,
+            Curve::Ease => Ease::Out,
+            Curve::Back => Ease::Back,
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bindings {
+    Standard,
+    Swapped, //B confirms and A goes back
+}
+
+pub const MAX_VOLUME: u8 = 4;
+pub const MAX_UNDO: u8 = 4;
+pub const MAX_HINTS: u8 = 3;
+pub const MAX_QUEUE: u8 = 4;
+
+//everything the player can change on the options screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub speed: Speed,
+    pub music_volume: u8, //0 to MAX_VOLUME
+    pub sfx_volume: u8,
+    pub theme: Theme,
+    pub spawns: Spawns,
+    pub undo_depth: u8, //moves the pause menu can take back
+    pub hints: u8, //hints allowed per game
+    pub bindings: Bindings,
+    pub reduced_motion: bool, //tiles jump straight to where they end up and screens fade rather than fly apart
+    pub queue_depth: u8, //moves that can be pressed ahead while tiles are still sliding
+    pub skip_animations: bool, //pressing ahead finishes the slide straight away
+    pub curve: Curve,
+    pub slide_by_distance: bool, //tiles going further take longer, otherwise every slide takes the same time
+    pub language: Language,
+    pub vision: Vision,
+    pub patterns: bool, //a shape in the corner of each tile that's different for every value
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            speed: Speed::Normal,
+            music_volume: MAX_VOLUME,
+            sfx_volume: MAX_VOLUME,
+            theme: Theme::Classic,
+            spawns: Spawns::Classic,
+            undo_depth: MAX_UNDO,
+            hints: 0,
+            bindings: Bindings::Standard,
+            reduced_motion: false,
+            queue_depth: 2,
+            skip_animations: false,
+            curve: Curve::Ease,
+            slide_by_distance: false,
+            language: Language::English,
+            vision: Vision::Normal,
+            patterns: false,
+        }
+    }
+}
+
+impl Settings {
+
+    //frames a tile takes to slide one move
+    pub fn tile_animation(&self) -> i32 {
+
+        if self.reduced_motion {
+            1
+        } else {
+            self.speed.frames()
+        }
+
+    }
+
+    //frames for a slide whose longest move crosses the given number of cells
+    pub fn slide_animation(&self, cells: i32) -> i32 {
+
+        if self.reduced_motion {
+            1
+        } else if self.slide_by_distance {
+            (self.speed.frames() / 2 * cells).max(1)
+        } else {
+            self.speed.frames()
+        }
+
+    }
+
+    //frames for the title animation and fades, reduced motion doesn't shorten these as fades are gentle anyway
+    pub fn start_animation(&self) -> i32 {
+        self.speed.frames() * 4
+    }
+
+    pub fn confirm(&self) -> Button {
+        match self.bindings {
+            Bindings::Standard => Button::A,
+            Bindings::Swapped => Button::B,
+        }
+    }
+
+    pub fn back(&self) -> Button {
+        match self.bindings {
+            Bindings::Standard => Button::B,
+            Bindings::Swapped => Button::A,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; SETTINGS_SIZE] {
+        [
+            self.speed as u8,
+            self.music_volume,
+            self.sfx_volume,
+            self.theme as u8,
+            self.spawns as u8,
+            self.undo_depth,
+            self.hints,
+            self.bindings as u8,
+            self.reduced_motion as u8,
+            self.queue_depth,
+            self.skip_animations as u8,
+            self.curve as u8,
+            self.slide_by_distance as u8,
+            self.language as u8,
+            self.vision as u8,
+            self.patterns as u8,
+        ]
+    }
+
+    //anything out of range falls back to the default for that setting
+    fn from_bytes(bytes: &[u8]) -> Self {
+
+        let default = Self::default();
+
+        Self {
+            speed: match bytes[0] {
+                0 => Speed::Slow,
+                2 => Speed::Fast,
+                _ => Speed::Normal,
+            },
+            music_volume: if bytes[1] <= MAX_VOLUME { bytes[1] } else { default.music_volume },
+            sfx_volume: if bytes[2] <= MAX_VOLUME { bytes[2] } else { default.sfx_volume },
+            theme: match bytes[3] {
+                1 => Theme::Dark,
+                2 => Theme::Contrast,
+                3 => Theme::Pastel,
+                4 => Theme::Green,
+                _ => Theme::Classic,
+            },
+            spawns: match bytes[4] {
+                1 => Spawns::Generous,
+                2 => Spawns::Stingy,
+                _ => Spawns::Classic,
+            },
+            undo_depth: if bytes[5] <= MAX_UNDO { bytes[5] } else { default.undo_depth },
+            hints: if bytes[6] <= MAX_HINTS { bytes[6] } else { default.hints },
+            bindings: if bytes[7] == 1 { Bindings::Swapped } else { Bindings::Standard },
+            reduced_motion: bytes[8] == 1,
+            queue_depth: if bytes[9] <= MAX_QUEUE { bytes[9] } else { default.queue_depth },
+            skip_animations: bytes[10] == 1,
+            curve: match bytes[11] {
+                0 => Curve::Linear,
+                2 => Curve::Back,
+                _ => Curve::Ease,
+            },
+            slide_by_distance: bytes[12] == 1,
+            language: if bytes[13] == 1 { Language::French } else { Language::English },
+            vision: match bytes[14] {
+                1 => Vision::Deuteranopia,
+                2 => Vision::Protanopia,
+                3 => Vision::Tritanopia,
+                _ => Vision::Normal,
+            },
+            patterns: bytes[15] == 1,
+        }
+
+    }
+
+}
+
+
+//save memory layout: magic, version, settings, then the high score
+const MAGIC: [u8; 4] = *b"2048";
+const VERSION: u8 = 5;
+const SETTINGS_SIZE: usize = 16;
+const SETTINGS_OFFSET: usize = MAGIC.len() + 1;
+const SCORE_OFFSET: usize = SETTINGS_OFFSET + SETTINGS_SIZE;
+const SAVE_SIZE: usize = SCORE_OFFSET + 4;
+
+//what's kept between power cycles
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SaveFile {
+    pub settings: Settings,
+    pub high_score: u32,
+}
+
+//the cartridge's battery backed sram
+pub struct Storage {
+    manager: SaveManager,
+}
+
+impl Storage {
+
+    pub fn new(mut manager: SaveManager) -> Self {
+        manager.init_sram();
+        Self { manager }
+    }
+
+    //defaults if the cartridge is blank, from another version or can't be read
+    pub fn load(&mut self) -> SaveFile {
+        self.read().ok().flatten().unwrap_or_default()
+    }
+
+    fn read(&mut self) -> Result<Option<SaveFile>, Error> {
+
+        let mut bytes = [0; SAVE_SIZE];
+        self.manager.access()?.read(0, &mut bytes)?;
+
+        if bytes[..MAGIC.len()] != MAGIC || bytes[MAGIC.len()] != VERSION {
+            return Ok(None);
+        }
+
+        let score = &bytes[SCORE_OFFSET..SAVE_SIZE];
+
+        Ok(Some(SaveFile {
+            settings: Settings::from_bytes(&bytes[SETTINGS_OFFSET..SCORE_OFFSET]),
+            high_score: u32::from_le_bytes([score[0], score[1], score[2], score[3]]),
+        }))
+
+    }
+
+    //failed writes are ignored, the game carries on with what's in memory
+    pub fn store(&mut self, file: &SaveFile) {
+        let _ = self.write(file);
+    }
+
+    fn write(&mut self, file: &SaveFile) -> Result<(), Error> {
+
+        let mut bytes = [0; SAVE_SIZE];
+        bytes[..MAGIC.len()].copy_from_slice(&MAGIC);
+        bytes[MAGIC.len()] = VERSION;
+        bytes[SETTINGS_OFFSET..SCORE_OFFSET].copy_from_slice(&file.settings.to_bytes());
+        bytes[SCORE_OFFSET..SAVE_SIZE].copy_from_slice(&file.high_score.to_le_bytes());
+
+        let mut data = self.manager.access()?;
+        data.prepare_write(0..SAVE_SIZE)?.write(0, &bytes)
+
+    }
+
+}
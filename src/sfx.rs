@@ -0,0 +1,123 @@
+use agb::fixnum::{num, Num};
+use agb::include_wav;
+use agb::sound::mixer::{ChannelId, Mixer, SoundChannel, SoundData};
+extern crate alloc;
+use alloc::vec::Vec;
+
+use crate::settings::{Settings, MAX_VOLUME};
+use crate::Event;
+
+
+static SLIDE: SoundData = include_wav!("sfx/slide.wav");
+static MERGE: SoundData = include_wav!("sfx/merge.wav");
+static SPAWN: SoundData = include_wav!("sfx/spawn.wav");
+static BLOCKED: SoundData = include_wav!("sfx/blocked.wav");
+static TARGET: SoundData = include_wav!("sfx/target.wav");
+static LOSE: SoundData = include_wav!("sfx/lose.wav");
+
+//effects get this many of the mixer's eight channels, the music keeps to the rest
+const CHANNELS: usize = 2;
+
+//each doubling of a merged tile plays the merge this much faster, about a semitone higher
+const MERGE_STEP: Num<u32, 8> = Num::from_raw(271); //1.06
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sound {
+    Slide,
+    Merge(u16),
+    Spawn,
+    Blocked,
+    Target,
+    GameOver,
+}
+
+impl Sound {
+
+    fn data(self) -> SoundData {
+        match self {
+            Sound::Slide => SLIDE,
+            Sound::Merge(_) => MERGE,
+            Sound::Spawn => SPAWN,
+            Sound::Blocked => BLOCKED,
+            Sound::Target => TARGET,
+            Sound::GameOver => LOSE,
+        }
+    }
+
+    //merges start at their recorded pitch for the smallest tiles and climb from there
+    fn speed(self) -> Num<u32, 8> {
+        match self {
+            Sound::Merge(value) => (1..value.max(2).ilog2()).fold(num!(1), |speed, _| speed * MERGE_STEP),
+            _ => num!(1),
+        }
+    }
+
+}
+
+impl From<Event> for Sound {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::Slide => Sound::Slide,
+            Event::Merge(value) => Sound::Merge(value),
+            Event::Spawn => Sound::Spawn,
+            Event::Blocked => Sound::Blocked,
+            Event::Target => Sound::Target,
+        }
+    }
+}
+
+//effects asked for during the frame, started together once the mixer is to hand
+pub struct Sounds {
+    queued: Vec<Sound>,
+    playing: Vec<ChannelId>, //oldest first
+}
+
+impl Sounds {
+
+    pub fn new() -> Self {
+        Self { queued: Vec::new(), playing: Vec::new() }
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        self.queued.push(sound);
+    }
+
+    //once a frame before the mixer does its work. with every channel in use the oldest effect is cut short
+    pub fn update(&mut self, mixer: &mut Mixer, settings: &Settings) {
+
+        self.playing.retain(|id| mixer.channel(id).is_some());
+
+        let volume = Num::<i16, 8>::new(settings.sfx_volume as i16) / MAX_VOLUME as i16;
+
+        for sound in self.queued.drain(..) {
+
+            if settings.sfx_volume == 0 {
+                continue;
+            }
+
+            if self.playing.len() >= CHANNELS {
+                let oldest = self.playing.remove(0);
+                if let Some(channel) = mixer.channel(&oldest) {
+                    channel.stop();
+                }
+            }
+
+            //high priority so the music never takes over an effect's channel
+            let mut channel = SoundChannel::new_high_priority(sound.data());
+            channel.volume(volume).playback(sound.speed());
+
+            if let Some(id) = mixer.play_sound(channel) {
+                self.playing.push(id);
+            }
+
+        }
+
+    }
+
+}
+
+impl Default for Sounds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
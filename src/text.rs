@@ -0,0 +1,158 @@
+use agb::display::tiled::{RegularBackground, RegularBackgroundId, RegularBackgroundSize, TileFormat};
+use agb::display::{GraphicsFrame, Priority};
+use agb::fixnum::Vector2D;
+
+use crate::background;
+extern crate alloc;
+use alloc::string::String;
+
+
+//characters in gfx/font.aseprite, one tile each in this order. lower case is drawn as upper case
+const GLYPHS: &str = " 0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ:.,'/%()!?+-ÉÈÊËÀÂÎÏÔÙÛÜÁÍÓÚÑÇ";
+
+//anything missing from the font
+const UNKNOWN: char = '?';
+
+//screen size in tiles
+pub const COLUMNS: i32 = 30;
+pub const ROWS: i32 = 20;
+
+//frames between characters appearing when text is typed out
+const TYPE_SPEED: i32 = 2;
+
+//the font is drawn once per colour, one strip under another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colour {
+    Dark,
+    Light,
+    Accent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Centre,
+    Right,
+}
+
+//what happens to each character over time, age is frames since the text first appeared
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    Still,
+    Typed(i32), //characters appear one after another
+    Shine(i32), //a highlight runs along the text
+}
+
+//how a piece of text is laid out and drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub colour: Colour,
+    pub align: Align,
+    pub effect: Effect,
+}
+
+impl Style {
+
+    pub const fn new(colour: Colour, align: Align) -> Self {
+        Self { colour, align, effect: Effect::Still }
+    }
+
+    pub const fn with(self, effect: Effect) -> Self {
+        Self { effect, ..self }
+    }
+
+}
+
+//a screen sized grid of 8x8 characters on its own background
+pub struct Text {
+    bg: RegularBackground,
+}
+
+impl Text {
+
+    pub fn new(priority: Priority) -> Self {
+
+        let mut text = Self { bg: RegularBackground::new(priority, RegularBackgroundSize::Background32x32, TileFormat::FourBpp) };
+        text.clear();
+        text
+
+    }
+
+    pub fn clear(&mut self) {
+
+        for y in 0..ROWS {
+            self.blank(Vector2D::new(0, y), COLUMNS);
+        }
+
+    }
+
+    pub fn blank(&mut self, start: Vector2D<i32>, width: i32) {
+        self.print(start, width, "", Style::new(Colour::Dark, Align::Left));
+    }
+
+    //writes text into a row of the given width in tiles, blanking the rest of the row.
+    //anything too long is cut short and ends in a full stop, like an abbreviation
+    pub fn print(&mut self, start: Vector2D<i32>, width: i32, text: &str, style: Style) {
+
+        let shortened: String;
+        let text = if text.chars().count() as i32 > width {
+            shortened = text.chars().take((width - 1).max(0) as usize).chain(['.']).collect();
+            &shortened
+        } else {
+            text
+        };
+
+        let length = text.chars().count() as i32;
+
+        let offset = match style.align {
+            Align::Left => 0,
+            Align::Centre => (width - length) / 2,
+            Align::Right => width - length,
+        };
+
+        for i in 0..width {
+
+            let ch = usize::try_from(i - offset).ok().and_then(|n| text.chars().nth(n)).unwrap_or(' ');
+            let at = i - offset; //position within the text, for effects
+
+            let (ch, colour) = match style.effect {
+                Effect::Still => (ch, style.colour),
+                Effect::Typed(age) => (if at < age / TYPE_SPEED { ch } else { ' ' }, style.colour),
+                Effect::Shine(age) => {
+                    //one character lit at a time, with a pause after the end before it starts again
+                    let lit = (age / TYPE_SPEED) % (length + width);
+                    (ch, if at == lit { Colour::Accent } else { style.colour })
+                },
+            };
+
+            self.bg.set_tile(start + Vector2D::new(i, 0), &background::font.tiles, background::font.tile_settings[glyph(ch, colour)]);
+
+        }
+
+    }
+
+    pub fn show(&mut self, frame: &mut GraphicsFrame) -> RegularBackgroundId {
+        self.show_scrolled(frame, Vector2D::new(0, 0))
+    }
+
+    //moves everything on the layer by whole pixels, positive scrolls it up and to the left
+    pub fn show_scrolled(&mut self, frame: &mut GraphicsFrame, scroll: Vector2D<i32>) -> RegularBackgroundId {
+        self.bg.set_scroll_pos(scroll);
+        self.bg.show(frame)
+    }
+
+}
+
+//index of a character's tile in the font
+fn glyph(ch: char, colour: Colour) -> usize {
+
+    let ch = ch.to_uppercase().next().unwrap_or(ch);
+    let count = GLYPHS.chars().count();
+
+    let index = GLYPHS.chars().position(|g| g == ch)
+        .or_else(|| GLYPHS.chars().position(|g| g == UNKNOWN))
+        .unwrap_or(0);
+
+    colour as usize * count + index
+
+}
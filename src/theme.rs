@@ -0,0 +1,228 @@
+use agb::display::object::{Size, SpriteVram};
+use agb::display::tiled::VRAM_MANAGER;
+use agb::display::{Palette16, Rgb, Rgb15};
+use agb::external::critical_section::{self, CriticalSection, Mutex};
+use agb::hash_map::HashMap;
+use core::cell::{Cell, RefCell};
+extern crate alloc;
+use alloc::boxed::Box;
+
+use crate::background;
+use crate::settings::{Theme, Vision};
+
+
+//the game boy's four shades of green, darkest first
+const DMG: [Rgb; 4] = [Rgb::new(15, 56, 15), Rgb::new(48, 98, 48), Rgb::new(139, 172, 15), Rgb::new(155, 188, 15)];
+
+impl Theme {
+
+    //every theme is worked out from the colours the art was drawn with, so there's no extra art to keep in step
+    fn recolour(self, colour: Rgb15) -> Rgb15 {
+
+        let Rgb { r, g, b } = Rgb::from(colour);
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+
+        let lightness = (r * 3 + g * 6 + b) / 10;
+        let grey = r.max(g).max(b) - r.min(g).min(b) < 40; //backgrounds, borders and text, as opposed to tile colours
+
+        let rgb = |r: i32, g: i32, b: i32| Rgb::new(r.clamp(0, 255) as u8, g.clamp(0, 255) as u8, b.clamp(0, 255) as u8).to_rgb15();
+
+        match self {
+            Theme::Classic => colour,
+            //light greys become dark ones, tiles keep their colour
+            Theme::Dark if grey => rgb(255 - r, 255 - g, 255 - b),
+            Theme::Dark => colour,
+            //greys pushed to black or white, colours pushed to full strength
+            Theme::Contrast if grey => if lightness > 160 { Rgb15::WHITE } else { Rgb15::BLACK },
+            Theme::Contrast => {
+                let (low, high) = (r.min(g).min(b), r.max(g).max(b));
+                let stretch = |c: i32| (c - low) * 255 / (high - low);
+                rgb(stretch(r), stretch(g), stretch(b))
+            },
+            //colours washed halfway to white
+            Theme::Pastel if grey => colour,
+            Theme::Pastel => rgb((r + 255) / 2, (g + 255) / 2, (b + 255) / 2),
+            Theme::Green => DMG[(lightness * 4 / 256) as usize].to_rgb15(),
+        }
+
+    }
+
+}
+
+//the colours each kind of colourblindness can still tell apart, anything coloured is moved along the line between them.
+//deuteranopia and protanopia both lose red against green and keep blue against yellow, so they share a line. where
+//they differ is protanopes seeing reds darker, and no red is left once a colour is moved onto the line
+const BLUE_YELLOW: (Rgb, Rgb) = (Rgb::new(0, 90, 200), Rgb::new(240, 200, 40));
+const RED_CYAN: (Rgb, Rgb) = (Rgb::new(220, 30, 60), Rgb::new(0, 190, 200));
+
+impl Vision {
+
+    //applied on top of the theme. tile colours run red to yellow as values grow, so hue picks the place on the line
+    //and lightness is kept, which keeps neighbouring values apart for those who can't see the hue change
+    fn recolour(self, colour: Rgb15) -> Rgb15 {
+
+        let Rgb { r, g, b } = Rgb::from(colour);
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+
+        let (high, low) = (r.max(g).max(b), r.min(g).min(b));
+        let grey = high - low < 40;
+
+        let (from, to) = match self {
+            Vision::Normal => return colour,
+            _ if grey => return colour,
+            Vision::Deuteranopia | Vision::Protanopia => BLUE_YELLOW,
+            Vision::Tritanopia => RED_CYAN,
+        };
+
+        //hue in degrees, reds and oranges up to 60 spread over the top two thirds, everything cooler below
+        let hue = if high == r {
+            (60 * (g - b) / (high - low)).rem_euclid(360)
+        } else if high == g {
+            120 + 60 * (b - r) / (high - low)
+        } else {
+            240 + 60 * (r - g) / (high - low)
+        };
+
+        let t = if hue <= 60 { 85 + hue * 170 / 60 } else { 85 - (hue - 60) * 85 / 300 };
+
+        let lerp = |a: u8, b: u8| a as i32 + (b as i32 - a as i32) * t / 255;
+        let (nr, ng, nb) = (lerp(from.r, to.r), lerp(from.g, to.g), lerp(from.b, to.b));
+
+        //scale back to the original lightness
+        let lightness = (r * 3 + g * 6 + b) / 10;
+        let current = ((nr * 3 + ng * 6 + nb) / 10).max(1);
+        let keep = |c: i32| (c * lightness / current).clamp(0, 255) as u8;
+
+        Rgb::new(keep(nr), keep(ng), keep(nb)).to_rgb15()
+
+    }
+
+}
+
+//a sprite as include_aseprite lays it out. tile_gfx is built with these in place of agb's own
+//so the art can be recoloured before it's loaded
+pub struct Sprite {
+    palette: &'static Palette16,
+    data: &'static [u8],
+    size: Size,
+}
+
+impl Sprite {
+
+    //unsafe like agb's, the data has to be aligned to 2 bytes. include_aseprite_inner takes care of that
+    pub const unsafe fn new(palette: &'static Palette16, data: &'static [u8], size: Size) -> Self {
+        Self { palette, data, size }
+    }
+
+    //agb's loader shares sprites and palettes out by address, so each recoloured copy is made once and kept.
+    //at most one copy per theme and vision, and only of the art that gets shown in them
+    fn themed(&'static self) -> &'static agb::display::object::Sprite {
+
+        critical_section::with(|cs| {
+
+            let (theme, vision) = CHOSEN.borrow(cs).get();
+            let key = (self as *const Sprite as usize, theme, vision);
+
+            if let Some(copy) = SPRITES.borrow_ref(cs).get(&key).copied() {
+                return copy;
+            }
+
+            let palette = themed_palette(cs, self.palette, theme, vision);
+            let copy: &'static _ = Box::leak(Box::new(unsafe { agb::display::object::Sprite::new(palette, self.data, self.size) }));
+
+            SPRITES.borrow_ref_mut(cs).insert(key, copy);
+            copy
+
+        })
+
+    }
+
+}
+
+impl From<&'static Sprite> for SpriteVram {
+    fn from(sprite: &'static Sprite) -> Self {
+        SpriteVram::from(sprite.themed())
+    }
+}
+
+//a run of sprites, include_aseprite makes one per tag
+pub struct Tag {
+    sprites: &'static [Sprite],
+}
+
+impl Tag {
+
+    //tags here are never animated by agb, so the direction is left out
+    pub const fn new(sprites: &'static [Sprite], _direction: usize) -> Self {
+        Self { sprites }
+    }
+
+    pub fn sprites(&self) -> &'static [Sprite] {
+        self.sprites
+    }
+
+    pub fn sprite(&self, index: usize) -> &'static Sprite {
+        &self.sprites[index]
+    }
+
+}
+
+//the colours sprites are loaded in, set by Palettes::update
+static CHOSEN: Mutex<Cell<(Theme, Vision)>> = Mutex::new(Cell::new((Theme::Classic, Vision::Normal)));
+
+//recoloured copies by original address, theme and vision
+type Copies<T> = Mutex<RefCell<HashMap<(usize, Theme, Vision), &'static T>>>;
+
+static SPRITES: Copies<agb::display::object::Sprite> = Mutex::new(RefCell::new(HashMap::new()));
+static PALETTES: Copies<Palette16> = Mutex::new(RefCell::new(HashMap::new()));
+
+fn themed_palette(cs: CriticalSection, palette: &'static Palette16, theme: Theme, vision: Vision) -> &'static Palette16 {
+
+    let key = (palette as *const Palette16 as usize, theme, vision);
+
+    if let Some(copy) = PALETTES.borrow_ref(cs).get(&key).copied() {
+        return copy;
+    }
+
+    let copy: &'static _ = Box::leak(Box::new(recolour(palette, theme, vision)));
+
+    PALETTES.borrow_ref_mut(cs).insert(key, copy);
+    copy
+
+}
+
+fn recolour(palette: &Palette16, theme: Theme, vision: Vision) -> Palette16 {
+    Palette16::new(core::array::from_fn(|i| vision.recolour(theme.recolour(palette.colour(i)))))
+}
+
+//puts the chosen theme's colours in. backgrounds are recoloured in palette ram, sprites as they're loaded
+pub struct Palettes {
+    applied: Option<(Theme, Vision)>, //None until the first update
+}
+
+impl Palettes {
+
+    pub fn new() -> Self {
+        Self { applied: None }
+    }
+
+    //once a frame before anything is shown. returns true when the colours change, anything already
+    //holding a sprite needs to load it again to pick them up
+    pub fn update(&mut self, theme: Theme, vision: Vision) -> bool {
+
+        if self.applied == Some((theme, vision)) {
+            return false;
+        }
+
+        for (i, palette) in background::PALETTES.iter().enumerate() {
+            VRAM_MANAGER.set_background_palette(i as u8, &recolour(palette, theme, vision));
+        }
+
+        critical_section::with(|cs| CHOSEN.borrow(cs).set((theme, vision)));
+        self.applied = Some((theme, vision));
+
+        true
+
+    }
+
+}
@@ -0,0 +1,155 @@
+use agb::fixnum::{num, Num};
+extern crate alloc;
+use alloc::vec::Vec;
+
+
+//how far through an animation something is, 0 at the start and 1 at the end
+pub type Progress = Num<i32, 8>;
+
+//shapes the progress of a tween
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ease {
+    Linear,
+    In, //starts slow and speeds up
+    Out, //starts fast and slows down
+    InOut,
+    Back, //runs a little past the end and settles back onto it
+}
+
+impl Ease {
+
+    pub fn apply(self, t: Progress) -> Progress {
+
+        let one: Progress = num!(1);
+
+        match self {
+            Ease::Linear => t,
+            Ease::In => t * t,
+            Ease::Out => one - (one - t) * (one - t),
+            Ease::InOut => {
+                if t < num!(0.5) {
+                    t * t * 2
+                } else {
+                    one - (one - t) * (one - t) * 2
+                }
+            },
+            Ease::Back => {
+                let overshoot: Progress = num!(1.70158);
+                let u = t - one;
+                one + u * u * u * (overshoot + one) + u * u * overshoot
+            },
+        }
+
+    }
+
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tween<K> {
+    key: K,
+    elapsed: i32,
+    delay: i32, //frames to wait before starting
+    duration: i32,
+    ease: Ease,
+}
+
+impl<K> Tween<K> {
+
+    fn progress(&self) -> Progress {
+
+        let t = (self.elapsed - self.delay).clamp(0, self.duration);
+
+        self.ease.apply(Progress::new(t) / self.duration.max(1))
+
+    }
+
+    fn is_done(&self) -> bool {
+        self.elapsed >= self.delay + self.duration
+    }
+
+}
+
+//every running animation of one owner, advanced once a frame by the main loop.
+//K names what is being animated, finished keys come back out of events
+#[derive(Debug, Clone)]
+pub struct Tweens<K> {
+    running: Vec<Tween<K>>,
+    finished: Vec<K>,
+}
+
+impl<K: Copy + PartialEq> Tweens<K> {
+
+    pub fn new() -> Self {
+        Self { running: Vec::new(), finished: Vec::new() }
+    }
+
+    //starting a key that's already running restarts it
+    pub fn start(&mut self, key: K, duration: i32, ease: Ease) {
+        self.start_after(key, 0, duration, ease);
+    }
+
+    pub fn start_after(&mut self, key: K, delay: i32, duration: i32, ease: Ease) {
+        self.running.retain(|t| t.key != key);
+        self.running.push(Tween { key, elapsed: 0, delay, duration, ease });
+    }
+
+    //one frame on, anything that reaches its end becomes an event
+    pub fn update(&mut self) {
+
+        for tween in self.running.iter_mut() {
+            tween.elapsed += 1;
+        }
+
+        self.collect();
+
+    }
+
+    //jumps everything to its end, they still come out as events
+    pub fn finish(&mut self) {
+
+        for tween in self.running.iter_mut() {
+            tween.elapsed = tween.delay + tween.duration;
+        }
+
+        self.collect();
+
+    }
+
+    fn collect(&mut self) {
+
+        let finished = &mut self.finished;
+
+        self.running.retain(|t| {
+            if t.is_done() {
+                finished.push(t.key);
+            }
+            !t.is_done()
+        });
+
+    }
+
+    //eased progress of a running tween, None once it's finished or if it was never started
+    pub fn progress(&self, key: K) -> Option<Progress> {
+        self.running.iter().find(|t| t.key == key).map(|t| t.progress())
+    }
+
+    pub fn is_running(&self, key: K) -> bool {
+        self.running.iter().any(|t| t.key == key)
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.running.is_empty()
+    }
+
+    //keys finished since the last call, in the order they finished
+    pub fn events(&mut self) -> Vec<K> {
+        core::mem::take(&mut self.finished)
+    }
+
+}
+
+impl<K: Copy + PartialEq> Default for Tweens<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}